@@ -0,0 +1,81 @@
+use core::ptr;
+use uefi::{
+    prelude::*,
+    table::cfg::{ACPI2_GUID, ACPI_GUID},
+};
+
+/// Size of the header common to the system description tables.
+const HEADER_SIZE: usize = 36;
+
+unsafe fn read<T: Copy>(addr: usize) -> T {
+    ptr::read_unaligned(addr as *const T)
+}
+
+/// Find an ACPI table by its signature, returning its physical address.
+pub fn find(st: &SystemTable<Boot>, sig: &[u8; 4]) -> Option<usize> {
+    let entries = st.config_table();
+    let rsdp = entries
+        .iter()
+        .find(|e| e.guid == ACPI2_GUID)
+        .or_else(|| entries.iter().find(|e| e.guid == ACPI_GUID))?
+        .address as usize;
+
+    unsafe {
+        if read::<[u8; 8]>(rsdp) != *b"RSD PTR " {
+            return None;
+        }
+
+        // Use XSDT on ACPI 2.0 or later, and RSDT otherwise.
+        let revision: u8 = read(rsdp + 15);
+        let xsdt: u64 = if revision >= 2 { read(rsdp + 24) } else { 0 };
+
+        let (root, entry_size) = if xsdt != 0 {
+            (xsdt as usize, 8)
+        } else {
+            (read::<u32>(rsdp + 16) as usize, 4)
+        };
+
+        let len: u32 = read(root + 4);
+        let count = (len as usize).saturating_sub(HEADER_SIZE) / entry_size;
+
+        (0..count)
+            .map(|i| {
+                let entry = root + HEADER_SIZE + i * entry_size;
+                if entry_size == 8 {
+                    read::<u64>(entry) as usize
+                } else {
+                    read::<u32>(entry) as usize
+                }
+            })
+            .find(|&table| read::<[u8; 4]>(table) == *sig)
+    }
+}
+
+/// Base address of the HPET registers from the HPET table.
+pub fn hpet(st: &SystemTable<Boot>) -> Option<usize> {
+    let table = find(st, b"HPET")?;
+
+    // Address of the Generic Address Structure of the base address.
+    let base: u64 = unsafe { read(table + 44) };
+
+    if base == 0 {
+        None
+    } else {
+        Some(base as usize)
+    }
+}
+
+/// I/O port of the ACPI PM timer from the FADT, with whether the counter is 32-bit wide.
+pub fn pm_timer(st: &SystemTable<Boot>) -> Option<(u16, bool)> {
+    let table = find(st, b"FACP")?;
+
+    let port: u32 = unsafe { read(table + 76) };
+    let flags: u32 = unsafe { read(table + 112) };
+
+    if port == 0 {
+        None
+    } else {
+        // TMR_VAL_EXT
+        Some((port as u16, flags & (1 << 8) != 0))
+    }
+}
@@ -0,0 +1,87 @@
+use crate::{fs::Volume, input::read_key};
+use alloc::{format, string::String, vec::Vec};
+use core::fmt::Write;
+use uefi::prelude::*;
+
+/// Setting in a config file, written as `name = value`.
+pub struct Entry {
+    pub line: usize,
+    pub name: String,
+    pub value: String,
+}
+
+impl Entry {
+    pub fn error(&self, msg: &str) -> String {
+        format!("line {}: {}", self.line, msg)
+    }
+}
+
+/// Config file read from the volume, with the errors found while parsing it.
+pub struct Config {
+    pub path: &'static str,
+    pub entries: Vec<Entry>,
+    pub errors: Vec<String>,
+}
+
+impl Config {
+    pub fn load(vol: &mut Volume, path: &'static str) -> Option<Self> {
+        let data = vol.read(path).ok()?;
+
+        let mut cfg = Self {
+            path,
+            entries: Vec::new(),
+            errors: Vec::new(),
+        };
+
+        let text = match core::str::from_utf8(&data) {
+            Ok(text) => text,
+            Err(e) => {
+                cfg.errors.push(format!("not a valid UTF-8 text: {}", e));
+                return Some(cfg);
+            }
+        };
+
+        for (i, line) in text.lines().enumerate() {
+            let line_no = i + 1;
+            let line = line.split('#').next().unwrap_or("").trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut kv = line.splitn(2, '=');
+
+            match (kv.next(), kv.next()) {
+                (Some(name), Some(value)) if !name.trim().is_empty() => cfg.entries.push(Entry {
+                    line: line_no,
+                    name: name.trim().into(),
+                    value: value.trim().into(),
+                }),
+                _ => cfg
+                    .errors
+                    .push(format!("line {}: expected `name = value`", line_no)),
+            }
+        }
+
+        Some(cfg)
+    }
+
+    /// Show errors on the console, if any, and wait for a key press.
+    pub fn report(&self, st: &SystemTable<Boot>) {
+        if self.errors.is_empty() {
+            return;
+        }
+
+        let out = st.stdout();
+
+        let _ = writeln!(out, "Errors in {}:", self.path);
+        for e in &self.errors {
+            let _ = writeln!(out, "  {}", e);
+        }
+        let _ = writeln!(out, "Press any key to continue");
+
+        while read_key(st).is_none() {
+            st.boot_services().stall(10_000);
+        }
+    }
+}
@@ -0,0 +1,61 @@
+/// Frame rate of the Game Boy in 1/10 frames per second (4194304 Hz / 70224 cycles).
+const NATIVE_FPS10: u64 = 597;
+
+/// Interval to report frame rates.
+const INTERVAL: u64 = 1_000_000;
+
+/// Frame rates over the last interval.
+pub struct Stats {
+    /// Emulated frames per second, in 1/10 units.
+    pub emulated: u64,
+    /// Frames presented to the screen per second, in 1/10 units.
+    pub presented: u64,
+    /// Emulation speed relative to the real hardware in percent.
+    pub speed: u64,
+}
+
+/// Counter of emulated and presented frames.
+pub struct FpsCounter {
+    start: u64,
+    emulated: u64,
+    presented: u64,
+}
+
+impl FpsCounter {
+    pub fn new() -> Self {
+        Self {
+            start: 0,
+            emulated: 0,
+            presented: 0,
+        }
+    }
+
+    pub fn emulated(&mut self) {
+        self.emulated += 1;
+    }
+
+    pub fn presented(&mut self) {
+        self.presented += 1;
+    }
+
+    /// Return the frame rates once per interval.
+    pub fn update(&mut self, now: u64) -> Option<Stats> {
+        let elapsed = now.wrapping_sub(self.start);
+
+        if elapsed < INTERVAL {
+            return None;
+        }
+
+        let stats = Stats {
+            emulated: self.emulated * 10_000_000 / elapsed,
+            presented: self.presented * 10_000_000 / elapsed,
+            speed: self.emulated * 10_000_000 / elapsed * 100 / NATIVE_FPS10,
+        };
+
+        self.start = now;
+        self.emulated = 0;
+        self.presented = 0;
+
+        Some(stats)
+    }
+}
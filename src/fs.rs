@@ -0,0 +1,194 @@
+use crate::loaded_image::LoadedImage;
+use alloc::{string::String, vec, vec::Vec};
+use log::*;
+use uefi::{
+    prelude::*,
+    proto::media::{
+        file::{Directory, File, FileAttribute, FileInfo, FileMode, FileType, RegularFile},
+        fs::SimpleFileSystem,
+    },
+    Handle,
+};
+
+/// Root directory of the volume the application was loaded from.
+pub struct Volume {
+    root: Directory,
+}
+
+fn to_string(name: &[u16]) -> String {
+    core::char::decode_utf16(name.iter().cloned())
+        .map(|c| c.unwrap_or('?'))
+        .collect()
+}
+
+impl Volume {
+    /// Open the volume of the device `image` was loaded from, rather than the first one
+    /// the firmware lists, which may be another disk.
+    pub fn open(bt: &BootServices, image: Handle) -> Option<Self> {
+        let loaded = match bt.handle_protocol::<LoadedImage>(image).log_warning() {
+            Ok(loaded) => unsafe { &*loaded.get() },
+            Err(e) => {
+                warn!("Couldn't open loaded image: {:?}", e.status());
+                return None;
+            }
+        };
+
+        let fs = match bt
+            .handle_protocol::<SimpleFileSystem>(loaded.device())
+            .log_warning()
+        {
+            Ok(fs) => fs,
+            Err(e) => {
+                warn!("Couldn't open the boot volume: {:?}", e.status());
+                return None;
+            }
+        };
+        let fs = unsafe { &mut *fs.get() };
+
+        match fs.open_volume().log_warning() {
+            Ok(root) => Some(Self { root }),
+            Err(e) => {
+                warn!("Couldn't open volume: {:?}", e.status());
+                None
+            }
+        }
+    }
+
+    fn open_file(&mut self, path: &str) -> Result<RegularFile, Status> {
+        let handle = self
+            .root
+            .open(path, FileMode::Read, FileAttribute::empty())
+            .log_warning()
+            .map_err(|e| e.status())?;
+
+        match handle.into_type().log_warning().map_err(|e| e.status())? {
+            FileType::Regular(file) => Ok(file),
+            FileType::Dir(_) => Err(Status::INVALID_PARAMETER),
+        }
+    }
+
+    fn create_file(&mut self, path: &str) -> Option<RegularFile> {
+        let handle = self
+            .root
+            .open(path, FileMode::CreateReadWrite, FileAttribute::empty())
+            .log_warning()
+            .ok()?;
+
+        match handle.into_type().log_warning().ok()? {
+            FileType::Regular(file) => Some(file),
+            FileType::Dir(_) => None,
+        }
+    }
+
+    fn open_dir(&mut self, path: &str) -> Option<Directory> {
+        let handle = self
+            .root
+            .open(path, FileMode::Read, FileAttribute::DIRECTORY)
+            .log_warning()
+            .ok()?;
+
+        match handle.into_type().log_warning().ok()? {
+            FileType::Dir(dir) => Some(dir),
+            FileType::Regular(_) => None,
+        }
+    }
+
+    /// Read the whole content of the file at `path`.
+    ///
+    /// Fails with `NOT_FOUND` if the file doesn't exist.
+    pub fn read(&mut self, path: &str) -> Result<Vec<u8>, Status> {
+        let mut file = self.open_file(path)?;
+        let mut data = Vec::new();
+        let mut chunk = vec![0; 4096];
+
+        loop {
+            let len = match file.read(&mut chunk).log_warning() {
+                Ok(len) => len,
+                Err(e) => {
+                    warn!("Couldn't read {}: {:?}", path, e.status());
+                    return Err(e.status());
+                }
+            };
+            if len == 0 {
+                break;
+            }
+            data.extend_from_slice(&chunk[..len]);
+        }
+
+        Ok(data)
+    }
+
+    /// Write `data` to the head of the file at `path`, creating it if it doesn't exist.
+    pub fn write(&mut self, path: &str, data: &[u8]) -> bool {
+        let mut file = match self.create_file(path) {
+            Some(file) => file,
+            None => {
+                warn!("Couldn't open {} for writing", path);
+                return false;
+            }
+        };
+
+        if let Err(e) = file.set_position(0).log_warning() {
+            warn!("Couldn't seek {}: {:?}", path, e.status());
+            return false;
+        }
+
+        if let Err(e) = file.write(data).log_warning() {
+            warn!("Couldn't write {}: {:?}", path, e.status());
+            return false;
+        }
+
+        if let Err(e) = file.flush().log_warning() {
+            warn!("Couldn't flush {}: {:?}", path, e.status());
+            return false;
+        }
+
+        true
+    }
+
+    /// Read at most `len` bytes from the head of the file at `path`.
+    pub fn read_head(&mut self, path: &str, len: usize) -> Option<Vec<u8>> {
+        let mut file = self.open_file(path).ok()?;
+        let mut data = vec![0; len];
+
+        match file.read(&mut data).log_warning() {
+            Ok(len) => {
+                data.truncate(len);
+                Some(data)
+            }
+            Err(e) => {
+                warn!("Couldn't read {}: {:?}", path, e.status());
+                None
+            }
+        }
+    }
+
+    /// List the names of regular files in the directory at `path`.
+    pub fn list(&mut self, path: &str) -> Vec<String> {
+        let mut names = Vec::new();
+
+        let mut dir = match self.open_dir(path) {
+            Some(dir) => dir,
+            None => return names,
+        };
+
+        let mut buf = vec![0; 1024];
+
+        loop {
+            let info: &mut FileInfo = match dir.read_entry(&mut buf).log_warning() {
+                Ok(Some(info)) => info,
+                Ok(None) => break,
+                Err(e) => {
+                    warn!("Couldn't read directory {}: {:?}", path, e.status());
+                    break;
+                }
+            };
+
+            if !info.attribute().contains(FileAttribute::DIRECTORY) {
+                names.push(to_string(info.file_name().to_u16_slice()));
+            }
+        }
+
+        names
+    }
+}
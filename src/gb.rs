@@ -1,41 +1,90 @@
-use alloc::{boxed::Box, vec, vec::Vec};
+use crate::{
+    fps::FpsCounter,
+    fs::Volume,
+    input::{Input, Joypad, Stroke},
+    keymap::Keymap,
+    menu, mode,
+    osd::Osd,
+    pacing::{Pacer, Pacing},
+    palette::Palettes,
+    rom,
+    save::Save,
+    serial::SerialLog,
+    settings::Settings,
+    time::Clock,
+    video::{self, Effect, Filter},
+};
+use alloc::{boxed::Box, format, vec, vec::Vec};
+use core::ops::Range;
 use log::*;
 use rgy::hardware::{Hardware as GbHardware, Key as GbKey, Stream, VRAM_HEIGHT, VRAM_WIDTH};
 use uefi::{
     prelude::*,
     proto::console::{
-        gop::{BltOp, BltPixel, BltRegion, GraphicsOutput},
+        gop::{BltOp, BltPixel, BltRegion, GraphicsOutput, PixelFormat},
         text::{Key, ScanCode},
     },
 };
-use uefi::{prelude::*, table::runtime::ResetType};
+use uefi::{prelude::*, table::runtime::ResetType, Handle};
 
-struct KeyInfo {
-    key: char,
-    time: u64,
-}
+/// Key to switch to the next palette.
+const PALETTE_KEY: ScanCode = ScanCode::FUNCTION_1;
+
+/// Key to toggle frame blending.
+const BLEND_KEY: ScanCode = ScanCode::FUNCTION_2;
+
+/// Key to toggle the frame rate display.
+const FPS_KEY: ScanCode = ScanCode::FUNCTION_3;
 
 struct Hardware {
     st: SystemTable<Boot>,
+    time: Clock,
+    pacer: Pacer,
+    /// An emulated frame ended and waits for pacing.
+    framedone: bool,
     vramsz: (usize, usize),
-    vram: [u32; VRAM_HEIGHT * VRAM_WIDTH],
-    vramlast: u64,
+    vrampos: (usize, usize),
+    /// Screen as drawn by the emulator.
+    vramraw: Vec<u32>,
+    /// Screen with the palette applied.
+    vram: Vec<u32>,
+    /// Screen of the previous frame, for blending.
+    vramprev: Vec<u32>,
+    /// Screen fed to scaling.
+    vramout: Vec<u32>,
+    /// Screen lines changed since the last draw.
+    vramdirty: [bool; VRAM_HEIGHT],
+    /// Screen lines changed in the previous frame, which still fade out when blending.
+    vramfading: [bool; VRAM_HEIGHT],
+    vramblend: bool,
+    /// Scaled image.
+    vrambuf: Vec<u32>,
+    /// Scaled image converted for Blt.
+    bltbuf: Vec<BltPixel>,
     vramscale: usize,
+    vramfilter: Filter,
+    vrameffect: Effect,
+    palettes: Palettes,
+    osd: Osd,
+    fps: FpsCounter,
+    fpsshow: bool,
+    fpslog: bool,
+    serial: SerialLog,
+    /// Pixel format of the linear framebuffer, if it can be written directly.
+    fbformat: Option<PixelFormat>,
+    fbstride: usize,
     keylast: u64,
-    pressed: Option<KeyInfo>,
-}
-
-fn tsc() -> u64 {
-    #[cfg(target_arch = "x86")]
-    use core::arch::x86::_rdtsc;
-    #[cfg(target_arch = "x86_64")]
-    use core::arch::x86_64::_rdtsc;
-
-    unsafe { _rdtsc() as u64 }
+    keymap: Keymap,
+    input: Input,
+    joypad: Joypad,
+    save: Option<Save>,
+    savelast: u64,
 }
 
 impl Drop for Hardware {
     fn drop(&mut self) {
+        self.flush_save();
+
         self.clear();
 
         info!("Shutting down in 3 seconds...");
@@ -46,23 +95,48 @@ impl Drop for Hardware {
     }
 }
 
-fn pix(col: u32) -> BltPixel {
-    let r = (col >> 16) as u8;
-    let g = (col >> 8) as u8;
-    let b = col as u8;
-    BltPixel::new(r, g, b)
-}
-
 impl Hardware {
-    fn new(st: SystemTable<Boot>) -> Self {
+    fn new(st: SystemTable<Boot>, settings: &Settings, keymap: Keymap, save: Option<Save>) -> Self {
+        let mut input = Input::new(&st);
+        input.register(keymap.keys());
+
+        let time = Clock::new(&st, settings.clock);
+        let serial = SerialLog::new(st.boot_services());
+        let pacer = Pacer::new(st.boot_services(), settings.pacing);
+
         Self {
             st,
+            time,
+            pacer,
+            framedone: false,
             vramsz: (0, 0),
-            vram: [0; VRAM_HEIGHT * VRAM_WIDTH],
-            vramlast: 0,
+            vrampos: (0, 0),
+            vramraw: vec![0; VRAM_HEIGHT * VRAM_WIDTH],
+            vram: vec![0; VRAM_HEIGHT * VRAM_WIDTH],
+            vramprev: vec![0; VRAM_HEIGHT * VRAM_WIDTH],
+            vramout: vec![0; VRAM_HEIGHT * VRAM_WIDTH],
+            vramdirty: [true; VRAM_HEIGHT],
+            vramfading: [false; VRAM_HEIGHT],
+            vramblend: false,
+            vrambuf: Vec::new(),
+            bltbuf: Vec::new(),
             vramscale: 1,
+            vramfilter: Filter::Nearest,
+            vrameffect: Effect::None,
+            palettes: Palettes::new("original", None),
+            osd: Osd::new(),
+            fps: FpsCounter::new(),
+            fpsshow: false,
+            fpslog: false,
+            serial,
+            fbformat: None,
+            fbstride: 0,
             keylast: 0,
-            pressed: None,
+            keymap,
+            input,
+            joypad: Joypad::new(),
+            save,
+            savelast: 0,
         }
     }
 
@@ -76,62 +150,228 @@ impl Hardware {
         unsafe { &mut *gop.get() }
     }
 
-    fn setup(&mut self) {
-        let mode = self
-            .gop()
-            .modes()
-            .map(|mode| mode.expect("Couldn't get graphics mode"))
-            // .find(|ref mode| {
-            //     let info = mode.info();
-            //     info.resolution() == (1024, 768)
-            // })
-            .nth(0)
-            .expect("No graphics mode");
+    fn setup(&mut self, settings: &Settings) {
+        let mode = mode::select(self.gop(), settings.mode);
 
         self.gop()
             .set_mode(&mode)
             .expect_success("Couldn't set graphics mode");
 
-        info!("{:?}", self.gop().current_mode_info().resolution());
+        let info = self.gop().current_mode_info();
+        let (resw, resh) = info.resolution();
 
-        let xscale = self.gop().current_mode_info().resolution().0 / VRAM_WIDTH;
-        let yscale = self.gop().current_mode_info().resolution().1 / VRAM_HEIGHT;
-        self.vramscale = xscale.min(yscale).max(1);
+        info!("{:?} {:?}", (resw, resh), info.pixel_format());
 
-        self.clear();
+        self.fbformat = match info.pixel_format() {
+            PixelFormat::RGB | PixelFormat::BGR => Some(info.pixel_format()),
+            _ => None,
+        };
+        self.fbstride = info.stride();
+
+        let (size, pos) = video::layout((resw, resh), settings.scaling);
+        self.vramscale = (size.0 / VRAM_WIDTH).max(1);
+        self.vramsz = size;
+        self.vrampos = pos;
+        self.vramfilter = settings.filter;
+        // Effects need at least two pixels per dot.
+        self.vrameffect = if self.vramscale > 1 {
+            settings.effect
+        } else {
+            Effect::None
+        };
+        self.palettes = Palettes::new(&settings.palette, settings.custom_palette);
+        self.vramblend = settings.blend;
+        self.osd.set_scale(self.vramscale / 2);
+        self.fpsshow = settings.fps;
+        self.fpslog = settings.fps_log;
+
+        self.vrambuf = vec![0; size.0 * size.1];
+        if self.fbformat.is_none() {
+            self.bltbuf = vec![BltPixel::new(0, 0, 0); size.0 * size.1];
+        }
+        self.vramdirty = [true; VRAM_HEIGHT];
+
+        self.letterbox();
     }
 
-    fn update_vram(&self) {
-        let scale = 1; //self.vramscale;
-
-        let w = VRAM_WIDTH;
-        let h = VRAM_HEIGHT;
-
-        for vramy in 0..scale {
-            for vramx in 0..scale {
-                let xbase = vramx * w;
-                let ybase = vramy * h;
-
-                let subvram: Vec<_> = (0..(w * h))
-                    .map(|i| {
-                        let x = ((i % w) + xbase) / scale;
-                        let y = ((i / w) + ybase) / scale;
-                        pix(self.vram[y * w + x])
-                    })
-                    .chain((0..w).map(|_| pix(0)))
-                    .collect();
-
-                let op = BltOp::BufferToVideo {
-                    buffer: &subvram,
-                    src: BltRegion::Full,
-                    dest: (xbase, ybase),
-                    dims: (w, h),
-                };
-                self.gop()
-                    .blt(op)
-                    .expect_success("Failed to fill screen with color");
+    /// Draw the screen lines changed since the last call.
+    fn update_vram(&mut self) {
+        let changed = self.vramdirty;
+
+        for line in 0..VRAM_HEIGHT {
+            if self.vramblend && self.vramfading[line] {
+                self.vramdirty[line] = true;
+            }
+
+            if !self.vramdirty[line] {
+                continue;
+            }
+
+            let range = line * VRAM_WIDTH..(line + 1) * VRAM_WIDTH;
+
+            if self.vramblend {
+                for i in range {
+                    self.vramout[i] = video::mix(self.vram[i], self.vramprev[i], 128);
+                }
+            } else {
+                self.vramout[range.clone()].copy_from_slice(&self.vram[range]);
+            }
+        }
+
+        for line in 0..VRAM_HEIGHT {
+            if changed[line] {
+                let range = line * VRAM_WIDTH..(line + 1) * VRAM_WIDTH;
+                self.vramprev[range.clone()].copy_from_slice(&self.vram[range]);
             }
         }
+        self.vramfading = changed;
+
+        let now = self.clock();
+
+        if let Some(stats) = self.fps.update(now) {
+            let msg = format!(
+                "{}.{} fps / {}.{} fps ({}%)",
+                stats.emulated / 10,
+                stats.emulated % 10,
+                stats.presented / 10,
+                stats.presented % 10,
+                stats.speed
+            );
+
+            if self.fpslog {
+                let line = format!("emulated / presented: {}", msg);
+                if !self.serial.line(&line) {
+                    info!("{}", line);
+                }
+            }
+            if self.fpsshow {
+                self.osd.set_status(Some(&msg));
+            }
+        }
+
+        // Only game lines count as presented, not redraws of the OSD.
+        let presented = self.vramdirty.iter().any(|&dirty| dirty);
+
+        if let Some(rows) = self.osd.update(now) {
+            for line in video::lines(rows, self.vramsz.1) {
+                self.vramdirty[line] = true;
+            }
+        }
+
+        let mut line = 0;
+
+        while line < VRAM_HEIGHT {
+            if !self.vramdirty[line] {
+                line += 1;
+                continue;
+            }
+
+            let start = line;
+            while line < VRAM_HEIGHT && self.vramdirty[line] {
+                self.vramdirty[line] = false;
+                line += 1;
+            }
+
+            self.draw_lines(start..line);
+        }
+
+        if presented {
+            self.fps.presented();
+        }
+    }
+
+    fn draw_lines(&mut self, lines: Range<usize>) {
+        let rows = video::rows(lines, self.vramsz.1);
+
+        video::scale(
+            &self.vramout,
+            &mut self.vrambuf,
+            self.vramsz,
+            self.vramfilter,
+            self.vrameffect,
+            rows.clone(),
+        );
+
+        self.osd.draw(&mut self.vrambuf, self.vramsz, rows.clone());
+
+        match self.fbformat {
+            Some(format) => self.write_fb(rows, format),
+            None => self.blt(rows),
+        }
+    }
+
+    /// Write the rows of the image directly to the linear framebuffer.
+    fn write_fb(&self, rows: Range<usize>, format: PixelFormat) {
+        let w = self.vramsz.0;
+        let (x, y) = self.vrampos;
+
+        let mut fb = self.gop().frame_buffer();
+        let base = fb.as_mut_ptr() as *mut u32;
+
+        for row in rows {
+            let src = &self.vrambuf[row * w..(row + 1) * w];
+            let dst = unsafe { base.add((y + row) * self.fbstride + x) };
+
+            for (i, &col) in src.iter().enumerate() {
+                unsafe { dst.add(i).write_volatile(video::fbpix(col, format)) };
+            }
+        }
+    }
+
+    /// Draw the rows of the image with Blt, for modes without a linear framebuffer.
+    fn blt(&mut self, rows: Range<usize>) {
+        let w = self.vramsz.0;
+        let (x, y) = self.vrampos;
+
+        let range = rows.start * w..rows.end * w;
+        for (dst, &src) in self.bltbuf[range.clone()]
+            .iter_mut()
+            .zip(&self.vrambuf[range])
+        {
+            *dst = video::pix(src);
+        }
+
+        let op = BltOp::BufferToVideo {
+            buffer: &self.bltbuf,
+            src: BltRegion::SubRectangle {
+                coords: (0, rows.start),
+                px_stride: w,
+            },
+            dest: (x, y + rows.start),
+            dims: (w, rows.end - rows.start),
+        };
+        self.gop()
+            .blt(op)
+            .expect_success("Failed to fill screen with color");
+    }
+
+    /// Fill the screen area around the image with black.
+    fn letterbox(&self) {
+        let (resw, resh) = self.gop().current_mode_info().resolution();
+        let (w, h) = self.vramsz;
+        let (x, y) = self.vrampos;
+
+        let bars = [
+            ((0, 0), (resw, y)),
+            ((0, y + h), (resw, resh.saturating_sub(y + h))),
+            ((0, y), (x, h)),
+            ((x + w, y), (resw.saturating_sub(x + w), h)),
+        ];
+
+        for &(dest, dims) in bars.iter() {
+            if dims.0 == 0 || dims.1 == 0 {
+                continue;
+            }
+
+            let op = BltOp::VideoFill {
+                color: BltPixel::new(0, 0, 0),
+                dest,
+                dims,
+            };
+            self.gop()
+                .blt(op)
+                .expect_success("Failed to fill screen with color");
+        }
     }
 
     fn clear(&self) {
@@ -146,49 +386,111 @@ impl Hardware {
             .expect_success("Failed to fill screen with color");
     }
 
-    fn get_key(&mut self) -> Option<Key> {
-        let comp = self.st.stdin().read_key().expect("Couldn't poll key input");
-        comp.expect("Couldn't extract key result")
+    /// Switch to the next palette and redraw the screen with it.
+    fn next_palette(&mut self) {
+        let name = self.palettes.next();
+        info!("Palette: {}", name);
+        let now = self.clock();
+        self.osd.show(&format!("Palette: {}", name), now);
+
+        for (dst, &src) in self.vram.iter_mut().zip(self.vramraw.iter()) {
+            *dst = self.palettes.map(src);
+        }
+        self.vramprev.copy_from_slice(&self.vram);
+        self.vramdirty = [true; VRAM_HEIGHT];
+    }
+
+    fn toggle_blend(&mut self) {
+        self.vramblend = !self.vramblend;
+
+        let msg = format!(
+            "Frame blending: {}",
+            if self.vramblend { "on" } else { "off" }
+        );
+        info!("{}", msg);
+        let now = self.clock();
+        self.osd.show(&msg, now);
+
+        self.vramdirty = [true; VRAM_HEIGHT];
+    }
+
+    fn toggle_fps(&mut self) {
+        self.fpsshow = !self.fpsshow;
+
+        if !self.fpsshow {
+            self.osd.set_status(None);
+        }
+    }
+
+    fn flush_save(&mut self) {
+        let saved = match self.save.as_mut() {
+            Some(save) => save.flush(&self.st),
+            None => false,
+        };
+
+        if saved {
+            let now = self.clock();
+            self.osd.show("Saved", now);
+        }
+    }
+
+    /// Hold or release the buttons bound to modifier keys by the modifier state.
+    fn update_modifiers(&mut self, shift: u32, now: u64) {
+        for (mask, key) in self.keymap.modifiers() {
+            if shift & mask != 0 {
+                self.joypad.hold(key, now);
+            } else {
+                self.joypad.release(key);
+            }
+        }
+    }
+
+    fn get_key(&mut self) -> Option<Stroke> {
+        self.input.read(&self.st)
     }
 }
 
 impl GbHardware for Hardware {
     fn joypad_pressed(&mut self, key: GbKey) -> bool {
-        false
+        self.joypad.pressed(key)
     }
 
     fn vram_update(&mut self, line: usize, buffer: &[u32]) {
-        for x in 0..buffer.len() {
-            self.vram[VRAM_WIDTH * line + x] = buffer[x];
+        let range = VRAM_WIDTH * line..VRAM_WIDTH * line + buffer.len();
+
+        if &self.vramraw[range.clone()] != buffer {
+            self.vramraw[range.clone()].copy_from_slice(buffer);
+            for (dst, &src) in self.vram[range].iter_mut().zip(buffer) {
+                *dst = self.palettes.map(src);
+            }
+            self.vramdirty[line] = true;
+        }
+
+        // Present the whole frame on entering VBlank.
+        if line == VRAM_HEIGHT - 1 {
+            self.fps.emulated();
+            self.update_vram();
+            self.framedone = true;
         }
     }
 
     fn sound_play(&mut self, stream: Box<dyn Stream>) {}
 
     fn load_ram(&mut self, size: usize) -> Vec<u8> {
-        vec![9; size]
+        match self.save.as_mut() {
+            Some(save) => save.load(&self.st, size),
+            None => vec![0; size],
+        }
     }
 
-    fn save_ram(&mut self, ram: &[u8]) {}
+    fn save_ram(&mut self, ram: &[u8]) {
+        if let Some(save) = self.save.as_mut() {
+            save.store(ram);
+        }
+    }
 
     fn clock(&mut self) -> u64 {
-        if cfg!(features = "uefi_time_source") {
-            let rt = self.st.runtime_services();
-            let t = rt
-                .get_time()
-                .expect("Couldn't get time")
-                .expect("Couln't extract time");
-
-            let days = days_from_civil(t.year() as i64, t.month() as i64, t.day() as i64);
-
-            (days as u64) * 24 * 3600_000_000
-                + (t.hour() as u64) * 3600_000_000
-                + (t.minute() as u64) * 60_000_000
-                + (t.second() as u64) * 1000_000
-                + (t.nanosecond() / 1000) as u64
-        } else {
-            tsc() / 1000
-        }
+        self.time.micros(&self.st)
     }
 
     fn send_byte(&mut self, b: u8) {}
@@ -198,60 +500,98 @@ impl GbHardware for Hardware {
     }
 
     fn sched(&mut self) -> bool {
-        if self.clock() - self.keylast >= 20_000 {
+        if self.clock() - self.keylast >= 20_000 || self.input.notified() {
             self.keylast = self.clock();
 
-            match self.get_key() {
-                Some(Key::Special(ScanCode::ESCAPE)) => return false,
-                Some(Key::Printable(code)) => {
-                    self.pressed = Some(KeyInfo {
-                        key: code.into(),
-                        time: self.clock(),
-                    });
-                    debug!("pressed {}", self.pressed.as_ref().unwrap().key);
-                }
-                _ => {
-                    let clk = self.clock();
-
-                    if let Some(k) = self.pressed.as_ref() {
-                        if clk.wrapping_sub(k.time) > 200_000_000 {
-                            self.pressed = None;
-                            debug!("released");
-                        }
+            let clk = self.clock();
+
+            // Drain all the queued key strokes so that simultaneous presses are all seen.
+            while let Some(stroke) = self.get_key() {
+                match stroke.key {
+                    Key::Special(ScanCode::ESCAPE) => return false,
+                    Key::Special(PALETTE_KEY) => {
+                        self.next_palette();
+                        continue;
+                    }
+                    Key::Special(BLEND_KEY) => {
+                        self.toggle_blend();
+                        continue;
+                    }
+                    Key::Special(FPS_KEY) => {
+                        self.toggle_fps();
+                        continue;
                     }
+                    _ => {}
+                }
+
+                if let Some(key) = self.keymap.get(&stroke.key) {
+                    self.joypad.press(key, clk);
+                }
+
+                if let Some(shift) = stroke.shift {
+                    self.update_modifiers(shift, clk);
                 }
             }
+
+            if let Some(shift) = self.input.idle_shift() {
+                self.update_modifiers(shift, clk);
+            }
+
+            self.joypad.update(clk);
         }
 
-        if self.clock() - self.vramlast >= 50_000 {
-            self.vramlast = self.clock();
-            self.update_vram();
+        let interval = self.save.as_ref().map_or(u64::MAX, Save::interval);
+        if self.clock() - self.savelast >= interval {
+            self.savelast = self.clock();
+            self.flush_save();
         }
 
+        let vblank = core::mem::replace(&mut self.framedone, false);
+        let Hardware {
+            st, time, pacer, ..
+        } = self;
+        pacer.sched(st.boot_services(), vblank, || time.micros(st));
+
         true
     }
 }
 
-#[allow(unused)]
-fn days_from_civil(y: i64, m: i64, d: i64) -> i64 {
-    let y = if m <= 2 { y - 1 } else { y };
-    let era = if y >= 0 { y } else { y - 399 } / 400;
-    let yoe = (y - era * 400);
-    let doy = (153 * (m + if m > 2 { -3 } else { 9 }) + 2) / 5 + d - 1;
-    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
-    era * 146097 + doe - 719468
-}
+pub fn run(image: Handle, st: SystemTable<Boot>) -> ! {
+    let mut vol = Volume::open(st.boot_services(), image);
+
+    let settings = vol
+        .as_mut()
+        .map(|vol| Settings::load(&st, vol))
+        .unwrap_or_default();
+
+    let keymap = vol
+        .as_mut()
+        .map(|vol| Keymap::load(&st, vol))
+        .unwrap_or_default();
+
+    let rom = vol
+        .as_mut()
+        .and_then(|vol| {
+            let entries = rom::scan(vol);
+            let index = menu::select(&st, &entries)?;
+            rom::load(vol, &entries[index].path)
+        })
+        .unwrap_or_else(rom::embedded);
+
+    let save = match (vol, rom.path.as_ref()) {
+        (Some(vol), Some(path)) => Some(Save::new(settings.save, vol, path)),
+        _ => None,
+    };
+
+    let mut hw = Hardware::new(st, &settings, keymap, save);
 
-pub fn run(st: SystemTable<Boot>) -> ! {
-    let mut hw = Hardware::new(st);
+    hw.setup(&settings);
 
-    hw.setup();
+    // The emulator busy-waits to keep the native speed, so leave it only to `spin`,
+    // letting the pacer be the only one waiting in the other modes.
+    let native_speed = settings.pacing == Pacing::Spin;
 
-    rgy::run(
-        rgy::Config::new().native_speed(true),
-        include_bytes!("roms/zelda.gb").to_vec(),
-        hw,
-    );
+    rgy::run(rgy::Config::new().native_speed(native_speed), rom.data, hw);
 
     loop {}
 }
@@ -0,0 +1,283 @@
+use crate::{
+    input_ex::{
+        InputEx, KeyData, KeyState as ExKeyState, CAPS_LOCK_ACTIVE, KEY_STATE_EXPOSED,
+        NUM_LOCK_ACTIVE, SCROLL_LOCK_ACTIVE, SHIFT_STATE_VALID, TOGGLE_STATE_VALID,
+    },
+    keymap::HostKey,
+};
+use core::sync::atomic::{AtomicBool, Ordering};
+use log::*;
+use rgy::hardware::Key as GbKey;
+use uefi::{
+    prelude::*,
+    proto::console::text::{Key, RawKey, ScanCode},
+};
+
+/// Time after the first key stroke to consider the key released if it doesn't repeat.
+/// Long enough to cover the typematic delay before repeating starts.
+const FIRST_RELEASE_TIMEOUT: u64 = 600_000;
+
+/// Time after the last key repeat to consider the key released.
+const RELEASE_TIMEOUT: u64 = 200_000;
+
+/// Time after the last report of a held modifier key to consider it released, for
+/// firmware that neither queues a stroke on its release nor reports the state while idle.
+const HOLD_RELEASE_TIMEOUT: u64 = 500_000;
+
+/// Poll a key from the console input without blocking.
+pub fn read_key(st: &SystemTable<Boot>) -> Option<Key> {
+    let comp = st.stdin().read_key().expect("Couldn't poll key input");
+    comp.expect("Couldn't extract key result")
+}
+
+/// Set by the firmware when a registered key stroke is entered.
+static NOTIFIED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn notify(_: *mut KeyData) -> Status {
+    NOTIFIED.store(true, Ordering::Relaxed);
+    Status::SUCCESS
+}
+
+fn shift_state(state: &ExKeyState) -> Option<u32> {
+    if state.shift_state & SHIFT_STATE_VALID != 0 {
+        Some(state.shift_state)
+    } else {
+        None
+    }
+}
+
+/// Key stroke read from the console.
+pub struct Stroke {
+    pub key: Key,
+    /// Modifier state of the stroke, if the firmware reports it.
+    pub shift: Option<u32>,
+}
+
+/// Console input, which uses Simple Text Input Ex if available.
+pub struct Input {
+    ex: Option<&'static mut InputEx>,
+    /// Modifier state reported while no stroke is queued, if the firmware reports it.
+    idle_shift: Option<u32>,
+}
+
+impl Input {
+    pub fn new(st: &SystemTable<Boot>) -> Self {
+        let ex = match st
+            .boot_services()
+            .locate_protocol::<InputEx>()
+            .log_warning()
+        {
+            Ok(ex) => unsafe { &mut *ex.get() },
+            Err(_) => {
+                info!("Simple Text Input Ex is not available; falling back to Simple Text Input");
+                return Self {
+                    ex: None,
+                    idle_shift: None,
+                };
+            }
+        };
+
+        // Setting the state also sets the lock keys, so keep their current state.
+        // Without the firmware reporting it, they end up turned off. A stroke left
+        // queued from before starting is dropped by this read.
+        let mut data = KeyData::empty();
+        let _ = ex.read_key_stroke(&mut data).log_warning();
+        let toggle = data.state.toggle_state;
+        let locks = if toggle & TOGGLE_STATE_VALID != 0 {
+            toggle & (SCROLL_LOCK_ACTIVE | NUM_LOCK_ACTIVE | CAPS_LOCK_ACTIVE)
+        } else {
+            0
+        };
+
+        // Ask the firmware to report strokes of modifier keys alone.
+        if ex
+            .set_state(TOGGLE_STATE_VALID | KEY_STATE_EXPOSED | locks)
+            .log_warning()
+            .is_err()
+        {
+            info!("Key state exposure is not supported");
+        }
+
+        Self {
+            ex: Some(ex),
+            idle_shift: None,
+        }
+    }
+
+    /// Register notifications on the keys so that their strokes are picked up
+    /// without waiting for the next poll.
+    pub fn register(&mut self, keys: impl Iterator<Item = HostKey>) {
+        let ex = match self.ex.as_mut() {
+            Some(ex) => ex,
+            None => return,
+        };
+
+        for key in keys {
+            let key = match key {
+                HostKey::Char(c) => RawKey {
+                    scan_code: ScanCode::NULL,
+                    unicode_char: c as u16,
+                },
+                HostKey::Scan(code) => RawKey {
+                    scan_code: code,
+                    unicode_char: 0,
+                },
+                HostKey::Modifier(_) => continue,
+            };
+
+            let data = KeyData {
+                key,
+                state: ExKeyState {
+                    shift_state: 0,
+                    toggle_state: 0,
+                },
+            };
+
+            if ex.register_key_notify(&data, notify).log_warning().is_err() {
+                warn!("Couldn't register key notification");
+                return;
+            }
+        }
+    }
+
+    /// Check and clear if any registered key was entered since the last call.
+    pub fn notified(&self) -> bool {
+        NOTIFIED.swap(false, Ordering::Relaxed)
+    }
+
+    /// Poll a key stroke without blocking.
+    pub fn read(&mut self, st: &SystemTable<Boot>) -> Option<Stroke> {
+        let ex = match self.ex.as_mut() {
+            Some(ex) => ex,
+            None => return read_key(st).map(|key| Stroke { key, shift: None }),
+        };
+
+        let mut data = KeyData::empty();
+        let read = ex
+            .read_key_stroke(&mut data)
+            .expect("Couldn't poll key input")
+            .expect("Couldn't extract key result");
+
+        if !read {
+            self.idle_shift = shift_state(&data.state);
+            return None;
+        }
+
+        Some(Stroke {
+            key: data.key.into(),
+            shift: shift_state(&data.state),
+        })
+    }
+
+    /// Take the modifier state reported when the last poll found no stroke queued.
+    ///
+    /// Firmware usually queues no stroke on releasing a modifier key, so this is how
+    /// the release is seen on firmware that reports the state while idle.
+    pub fn idle_shift(&mut self) -> Option<u32> {
+        self.idle_shift.take()
+    }
+}
+
+#[derive(Clone, Copy)]
+struct KeyState {
+    pressed: u64,
+    last: u64,
+    /// The press state is reported with the modifier state, not guessed from key repeats.
+    held: bool,
+}
+
+/// Press state of each joypad button.
+///
+/// The console input only reports key strokes, so a button is usually considered
+/// held while its key keeps repeating, and released when repeats stop. Buttons bound
+/// to modifier keys follow the modifier state reported by the firmware, and are
+/// released on a timeout when the firmware stops reporting them.
+pub struct Joypad {
+    keys: [Option<KeyState>; 8],
+}
+
+fn index(key: GbKey) -> usize {
+    match key {
+        GbKey::Right => 0,
+        GbKey::Left => 1,
+        GbKey::Up => 2,
+        GbKey::Down => 3,
+        GbKey::A => 4,
+        GbKey::B => 5,
+        GbKey::Select => 6,
+        GbKey::Start => 7,
+    }
+}
+
+impl Joypad {
+    pub fn new() -> Self {
+        Self { keys: [None; 8] }
+    }
+
+    pub fn press(&mut self, key: GbKey, now: u64) {
+        let state = &mut self.keys[index(key)];
+
+        match state {
+            Some(s) => s.last = now,
+            None => {
+                debug!("pressed {:?}", key);
+                *state = Some(KeyState {
+                    pressed: now,
+                    last: now,
+                    held: false,
+                });
+            }
+        }
+    }
+
+    /// Press the button until `release` is called or the hold is no longer reported.
+    pub fn hold(&mut self, key: GbKey, now: u64) {
+        let state = &mut self.keys[index(key)];
+
+        match state {
+            Some(s) if s.held => s.last = now,
+            _ => {
+                debug!("held {:?}", key);
+                *state = Some(KeyState {
+                    pressed: now,
+                    last: now,
+                    held: true,
+                });
+            }
+        }
+    }
+
+    /// Release the button pressed by `hold`.
+    pub fn release(&mut self, key: GbKey) {
+        let state = &mut self.keys[index(key)];
+
+        if let Some(KeyState { held: true, .. }) = state {
+            debug!("released {:?}", key);
+            *state = None;
+        }
+    }
+
+    /// Release buttons whose key stopped repeating or whose hold stopped being reported.
+    pub fn update(&mut self, now: u64) {
+        for state in self.keys.iter_mut() {
+            if let Some(s) = state {
+                let timeout = if s.held {
+                    HOLD_RELEASE_TIMEOUT
+                } else if s.pressed == s.last {
+                    FIRST_RELEASE_TIMEOUT
+                } else {
+                    RELEASE_TIMEOUT
+                };
+
+                if now.wrapping_sub(s.last) > timeout {
+                    debug!("released");
+                    *state = None;
+                }
+            }
+        }
+    }
+
+    pub fn pressed(&self, key: GbKey) -> bool {
+        self.keys[index(key)].is_some()
+    }
+}
@@ -0,0 +1,85 @@
+//! Simple Text Input Ex protocol, which gives modifier states of key strokes.
+
+use core::ffi::c_void;
+use uefi::{
+    prelude::*,
+    proto::{console::text::RawKey, Protocol},
+    unsafe_guid, Event,
+};
+
+pub const SHIFT_STATE_VALID: u32 = 0x8000_0000;
+pub const RIGHT_SHIFT_PRESSED: u32 = 0x0000_0001;
+pub const LEFT_SHIFT_PRESSED: u32 = 0x0000_0002;
+pub const RIGHT_CONTROL_PRESSED: u32 = 0x0000_0004;
+pub const LEFT_CONTROL_PRESSED: u32 = 0x0000_0008;
+pub const RIGHT_ALT_PRESSED: u32 = 0x0000_0010;
+pub const LEFT_ALT_PRESSED: u32 = 0x0000_0020;
+pub const RIGHT_LOGO_PRESSED: u32 = 0x0000_0040;
+pub const LEFT_LOGO_PRESSED: u32 = 0x0000_0080;
+
+pub const TOGGLE_STATE_VALID: u8 = 0x80;
+pub const KEY_STATE_EXPOSED: u8 = 0x40;
+pub const SCROLL_LOCK_ACTIVE: u8 = 0x01;
+pub const NUM_LOCK_ACTIVE: u8 = 0x02;
+pub const CAPS_LOCK_ACTIVE: u8 = 0x04;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct KeyState {
+    pub shift_state: u32,
+    pub toggle_state: u8,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct KeyData {
+    pub key: RawKey,
+    pub state: KeyState,
+}
+
+impl KeyData {
+    pub fn empty() -> Self {
+        unsafe { core::mem::zeroed() }
+    }
+}
+
+pub type KeyNotify = extern "C" fn(data: *mut KeyData) -> Status;
+
+#[allow(unused)]
+#[repr(C)]
+#[unsafe_guid("dd9e7534-7762-4698-8c14-f58517a625aa")]
+#[derive(Protocol)]
+pub struct InputEx {
+    reset: extern "C" fn(this: &mut InputEx, extended_verification: bool) -> Status,
+    read_key_stroke: extern "C" fn(this: &mut InputEx, data: *mut KeyData) -> Status,
+    wait_for_key: Event,
+    set_state: extern "C" fn(this: &mut InputEx, toggle_state: *const u8) -> Status,
+    register_key_notify: extern "C" fn(
+        this: &mut InputEx,
+        data: *const KeyData,
+        func: KeyNotify,
+        handle: *mut *mut c_void,
+    ) -> Status,
+    unregister_key_notify: extern "C" fn(this: &mut InputEx, handle: *mut c_void) -> Status,
+}
+
+impl InputEx {
+    /// Read a key stroke with its modifier state into `data`. Returns `false` if no
+    /// stroke is queued, in which case some firmware still fills in the current state.
+    pub fn read_key_stroke(&mut self, data: &mut KeyData) -> uefi::Result<bool> {
+        match (self.read_key_stroke)(self, data) {
+            Status::NOT_READY => Ok(false.into()),
+            other => other.into_with_val(|| true),
+        }
+    }
+
+    pub fn set_state(&mut self, toggle_state: u8) -> uefi::Result {
+        (self.set_state)(self, &toggle_state).into()
+    }
+
+    /// Register a function called by the firmware when the key stroke is entered.
+    pub fn register_key_notify(&mut self, data: &KeyData, func: KeyNotify) -> uefi::Result {
+        let mut handle = core::ptr::null_mut();
+        (self.register_key_notify)(self, data, func, &mut handle).into()
+    }
+}
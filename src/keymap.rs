@@ -0,0 +1,196 @@
+use crate::{config::Config, fs::Volume, input_ex::*};
+use alloc::{format, vec, vec::Vec};
+use rgy::hardware::Key as GbKey;
+use uefi::{
+    prelude::*,
+    proto::console::text::{Key, ScanCode},
+};
+
+const KEYMAP_PATH: &str = "stickboy\\keys.cfg";
+
+pub const BACKSPACE: char = '\u{8}';
+pub const CARRIAGE_RETURN: char = '\r';
+
+/// Keys handled before the keymap: ESC to quit and the function key hotkeys.
+const RESERVED: [ScanCode; 4] = [
+    ScanCode::ESCAPE,
+    ScanCode::FUNCTION_1,
+    ScanCode::FUNCTION_2,
+    ScanCode::FUNCTION_3,
+];
+
+/// Console key which can be bound to a joypad button.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum HostKey {
+    Char(char),
+    Scan(ScanCode),
+    /// Modifier key, given as a shift state bit. Only usable with Simple Text Input Ex.
+    Modifier(u32),
+}
+
+impl HostKey {
+    pub fn from_key(key: &Key) -> Self {
+        match key {
+            Key::Printable(code) => HostKey::Char(char::from(*code).to_ascii_lowercase()),
+            Key::Special(code) => HostKey::Scan(*code),
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        let scan = match s.to_ascii_uppercase().as_str() {
+            "ENTER" => return Some(HostKey::Char(CARRIAGE_RETURN)),
+            "BACKSPACE" => return Some(HostKey::Char(BACKSPACE)),
+            "SPACE" => return Some(HostKey::Char(' ')),
+            "TAB" => return Some(HostKey::Char('\t')),
+            "LSHIFT" => return Some(HostKey::Modifier(LEFT_SHIFT_PRESSED)),
+            "RSHIFT" => return Some(HostKey::Modifier(RIGHT_SHIFT_PRESSED)),
+            "LCTRL" => return Some(HostKey::Modifier(LEFT_CONTROL_PRESSED)),
+            "RCTRL" => return Some(HostKey::Modifier(RIGHT_CONTROL_PRESSED)),
+            "LALT" => return Some(HostKey::Modifier(LEFT_ALT_PRESSED)),
+            "RALT" => return Some(HostKey::Modifier(RIGHT_ALT_PRESSED)),
+            "LLOGO" => return Some(HostKey::Modifier(LEFT_LOGO_PRESSED)),
+            "RLOGO" => return Some(HostKey::Modifier(RIGHT_LOGO_PRESSED)),
+            "UP" => ScanCode::UP,
+            "DOWN" => ScanCode::DOWN,
+            "LEFT" => ScanCode::LEFT,
+            "RIGHT" => ScanCode::RIGHT,
+            "HOME" => ScanCode::HOME,
+            "END" => ScanCode::END,
+            "INSERT" => ScanCode::INSERT,
+            "DELETE" => ScanCode::DELETE,
+            "PAGE_UP" => ScanCode::PAGE_UP,
+            "PAGE_DOWN" => ScanCode::PAGE_DOWN,
+            "F1" => ScanCode::FUNCTION_1,
+            "F2" => ScanCode::FUNCTION_2,
+            "F3" => ScanCode::FUNCTION_3,
+            "F4" => ScanCode::FUNCTION_4,
+            "F5" => ScanCode::FUNCTION_5,
+            "F6" => ScanCode::FUNCTION_6,
+            "F7" => ScanCode::FUNCTION_7,
+            "F8" => ScanCode::FUNCTION_8,
+            "F9" => ScanCode::FUNCTION_9,
+            "F10" => ScanCode::FUNCTION_10,
+            "F11" => ScanCode::FUNCTION_11,
+            "F12" => ScanCode::FUNCTION_12,
+            _ => {
+                let mut chars = s.chars();
+                return match (chars.next(), chars.next()) {
+                    (Some(c), None) if c.is_ascii_graphic() => {
+                        Some(HostKey::Char(c.to_ascii_lowercase()))
+                    }
+                    _ => None,
+                };
+            }
+        };
+
+        Some(HostKey::Scan(scan))
+    }
+}
+
+fn parse_button(s: &str) -> Option<GbKey> {
+    match s.to_ascii_lowercase().as_str() {
+        "up" => Some(GbKey::Up),
+        "down" => Some(GbKey::Down),
+        "left" => Some(GbKey::Left),
+        "right" => Some(GbKey::Right),
+        "a" => Some(GbKey::A),
+        "b" => Some(GbKey::B),
+        "start" => Some(GbKey::Start),
+        "select" => Some(GbKey::Select),
+        _ => None,
+    }
+}
+
+/// Bindings from console keys to joypad buttons.
+pub struct Keymap {
+    bindings: Vec<(HostKey, GbKey)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self {
+            bindings: vec![
+                (HostKey::Scan(ScanCode::UP), GbKey::Up),
+                (HostKey::Scan(ScanCode::DOWN), GbKey::Down),
+                (HostKey::Scan(ScanCode::LEFT), GbKey::Left),
+                (HostKey::Scan(ScanCode::RIGHT), GbKey::Right),
+                (HostKey::Char('z'), GbKey::A),
+                (HostKey::Char('x'), GbKey::B),
+                (HostKey::Char(CARRIAGE_RETURN), GbKey::Start),
+                (HostKey::Char(BACKSPACE), GbKey::Select),
+            ],
+        }
+    }
+}
+
+impl Keymap {
+    /// Load bindings from `\stickboy\keys.cfg`, which has lines like `a = z` or `up = UP, w`.
+    ///
+    /// Buttons not mentioned in the file keep the default bindings, except for keys
+    /// bound to other buttons in the file. Errors are shown on the console.
+    pub fn load(st: &SystemTable<Boot>, vol: &mut Volume) -> Self {
+        let mut keymap = Self::default();
+
+        let mut cfg = match Config::load(vol, KEYMAP_PATH) {
+            Some(cfg) => cfg,
+            None => return keymap,
+        };
+
+        let mut bindings = Vec::new();
+
+        for entry in &cfg.entries {
+            let button = match parse_button(&entry.name) {
+                Some(button) => button,
+                None => {
+                    cfg.errors
+                        .push(entry.error(&format!("unknown button `{}`", entry.name)));
+                    continue;
+                }
+            };
+
+            for key in entry.value.split(',').map(|s| s.trim()) {
+                match HostKey::parse(key) {
+                    Some(HostKey::Scan(code)) if RESERVED.contains(&code) => cfg
+                        .errors
+                        .push(entry.error(&format!("key `{}` is reserved", key))),
+                    Some(key) => bindings.push((key, button)),
+                    None => cfg
+                        .errors
+                        .push(entry.error(&format!("unknown key `{}`", key))),
+                }
+            }
+        }
+
+        for &(key, button) in &bindings {
+            keymap.bindings.retain(|&(k, b)| b != button && k != key);
+        }
+        keymap.bindings.extend(bindings);
+
+        cfg.report(st);
+
+        keymap
+    }
+
+    /// Map a console key to a joypad button.
+    pub fn get(&self, key: &Key) -> Option<GbKey> {
+        let key = HostKey::from_key(key);
+
+        self.bindings
+            .iter()
+            .find(|&&(k, _)| k == key)
+            .map(|&(_, button)| button)
+    }
+
+    /// Iterate keys bound to buttons.
+    pub fn keys<'a>(&'a self) -> impl Iterator<Item = HostKey> + 'a {
+        self.bindings.iter().map(|&(k, _)| k)
+    }
+
+    /// Iterate buttons bound to modifier keys, with the shift state bit of the modifier.
+    pub fn modifiers<'a>(&'a self) -> impl Iterator<Item = (u32, GbKey)> + 'a {
+        self.bindings.iter().filter_map(|&(k, button)| match k {
+            HostKey::Modifier(mask) => Some((mask, button)),
+            _ => None,
+        })
+    }
+}
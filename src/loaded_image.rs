@@ -0,0 +1,31 @@
+//! Loaded Image protocol, which tells the device the application was loaded from.
+
+use core::ffi::c_void;
+use uefi::{prelude::*, proto::Protocol, table::boot::MemoryType, unsafe_guid, Handle};
+
+#[allow(unused)]
+#[repr(C)]
+#[unsafe_guid("5b1b31a1-9562-11d2-8e3f-00a0c969723b")]
+#[derive(Protocol)]
+pub struct LoadedImage {
+    revision: u32,
+    parent_handle: Handle,
+    system_table: *const c_void,
+    device_handle: Handle,
+    file_path: *const c_void,
+    reserved: *const c_void,
+    load_options_size: u32,
+    load_options: *const c_void,
+    image_base: *const c_void,
+    image_size: u64,
+    image_code_type: MemoryType,
+    image_data_type: MemoryType,
+    unload: extern "C" fn(image_handle: Handle) -> Status,
+}
+
+impl LoadedImage {
+    /// Handle of the device the image was loaded from.
+    pub fn device(&self) -> Handle {
+        self.device_handle
+    }
+}
@@ -6,18 +6,38 @@
 
 extern crate alloc;
 
+mod acpi;
+mod config;
+mod font;
+mod fps;
+mod fs;
 mod gb;
+mod input;
+mod input_ex;
+mod keymap;
+mod loaded_image;
+mod menu;
+mod mode;
+mod osd;
+mod pacing;
+mod palette;
+mod rom;
+mod save;
+mod serial;
+mod settings;
+mod time;
+mod video;
 
 use log::*;
 use uefi::prelude::*;
 
 #[no_mangle]
-pub extern "C" fn efi_main(_image: uefi::Handle, st: SystemTable<Boot>) -> Status {
+pub extern "C" fn efi_main(image: uefi::Handle, st: SystemTable<Boot>) -> Status {
     uefi_services::init(&st).expect_success("Failed to initialize utilities");
 
     st.stdout()
         .reset(false)
         .expect_success("Failed to reset stdout");
 
-    gb::run(st);
+    gb::run(image, st);
 }
@@ -0,0 +1,50 @@
+use crate::{input::read_key, keymap::CARRIAGE_RETURN, rom::Entry};
+use core::fmt::Write;
+use uefi::{
+    prelude::*,
+    proto::console::text::{Key, ScanCode},
+};
+
+fn draw(st: &SystemTable<Boot>, entries: &[Entry], cursor: usize) {
+    let out = st.stdout();
+
+    out.clear().expect_success("Failed to clear screen");
+
+    let _ = writeln!(out, "stickboy - select a game\n");
+    for (i, entry) in entries.iter().enumerate() {
+        let mark = if i == cursor { ">" } else { " " };
+        let _ = writeln!(out, " {} {:<16} {}", mark, entry.title, entry.path);
+    }
+    let _ = writeln!(out, "\nUp/Down: move, Enter: start");
+}
+
+/// Show the ROM list on the console and return the index of the chosen one.
+pub fn select(st: &SystemTable<Boot>, entries: &[Entry]) -> Option<usize> {
+    if entries.is_empty() {
+        return None;
+    }
+
+    let mut cursor = 0;
+
+    draw(st, entries, cursor);
+
+    loop {
+        match read_key(st) {
+            Some(Key::Special(ScanCode::UP)) => {
+                cursor = cursor.checked_sub(1).unwrap_or(entries.len() - 1);
+            }
+            Some(Key::Special(ScanCode::DOWN)) => {
+                cursor = (cursor + 1) % entries.len();
+            }
+            Some(Key::Printable(code)) if char::from(code) == CARRIAGE_RETURN => {
+                return Some(cursor);
+            }
+            _ => {
+                st.boot_services().stall(10_000);
+                continue;
+            }
+        }
+
+        draw(st, entries, cursor);
+    }
+}
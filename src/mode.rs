@@ -0,0 +1,70 @@
+use alloc::vec::Vec;
+use log::*;
+use rgy::hardware::{VRAM_HEIGHT, VRAM_WIDTH};
+use uefi::proto::console::gop::{GraphicsOutput, Mode};
+
+fn scale((w, h): (usize, usize)) -> usize {
+    (w / VRAM_WIDTH).min(h / VRAM_HEIGHT)
+}
+
+/// Choose the graphics mode to use.
+///
+/// The resolution given by `preferred` is used if available. Otherwise, the mode
+/// giving the largest integer multiple of the screen is chosen, preferring the
+/// current mode, which is usually the native panel resolution set by the firmware,
+/// and then the smallest resolution to reduce the drawing cost.
+pub fn select(gop: &mut GraphicsOutput, preferred: Option<(usize, usize)>) -> Mode {
+    let native = gop.current_mode_info().resolution();
+
+    let modes: Vec<_> = gop
+        .modes()
+        .map(|mode| mode.expect("Couldn't get graphics mode"))
+        .collect();
+
+    info!("Graphics modes (current {}x{}):", native.0, native.1);
+    for mode in &modes {
+        let res = mode.info().resolution();
+        info!("  {}x{} (x{})", res.0, res.1, scale(res));
+    }
+
+    let preferred = preferred.and_then(|pref| {
+        let index = modes
+            .iter()
+            .position(|mode| mode.info().resolution() == pref);
+        if index.is_none() {
+            warn!("Mode {}x{} is not available", pref.0, pref.1);
+        }
+        index
+    });
+
+    let index = preferred.unwrap_or_else(|| {
+        let best = modes
+            .iter()
+            .map(|mode| scale(mode.info().resolution()))
+            .max()
+            .unwrap_or(0);
+
+        let candidates = || {
+            modes
+                .iter()
+                .enumerate()
+                .filter(move |(_, mode)| scale(mode.info().resolution()) == best)
+        };
+
+        candidates()
+            .find(|(_, mode)| mode.info().resolution() == native)
+            .or_else(|| {
+                candidates().min_by_key(|(_, mode)| {
+                    let (w, h) = mode.info().resolution();
+                    w * h
+                })
+            })
+            .map(|(i, _)| i)
+            .expect("No graphics mode")
+    });
+
+    let mode = modes.into_iter().nth(index).expect("No graphics mode");
+    let res = mode.info().resolution();
+    info!("Using {}x{}", res.0, res.1);
+    mode
+}
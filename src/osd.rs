@@ -0,0 +1,135 @@
+use crate::{font, video};
+use alloc::{string::String, vec::Vec};
+use core::ops::Range;
+
+/// Time to show a message.
+const MESSAGE_DURATION: u64 = 2_000_000;
+
+/// Space between the screen edge and the text box, in font dots.
+const MARGIN: usize = 2;
+
+/// Colour of the text.
+const TEXT_COLOR: u32 = 0xff_ffff;
+
+/// On-screen display drawn on top of the scaled image.
+pub struct Osd {
+    /// Size of a font dot in pixels.
+    scale: usize,
+    /// Message and the time it expires.
+    message: Option<(String, u64)>,
+    /// Persistent status shown in the top-right corner.
+    status: Option<String>,
+    /// Something was drawn in the last frame, which needs to be erased.
+    drawn: bool,
+}
+
+impl Osd {
+    pub fn new() -> Self {
+        Self {
+            scale: 1,
+            message: None,
+            status: None,
+            drawn: false,
+        }
+    }
+
+    pub fn set_scale(&mut self, scale: usize) {
+        self.scale = scale.max(1);
+    }
+
+    /// Show a transient message.
+    pub fn show(&mut self, text: &str, now: u64) {
+        self.message = Some((text.into(), now + MESSAGE_DURATION));
+    }
+
+    pub fn set_status(&mut self, text: Option<&str>) {
+        self.status = text.map(|text| text.into());
+    }
+
+    /// Rows of the image where texts are drawn.
+    fn rows(&self) -> Range<usize> {
+        0..(MARGIN + font::HEIGHT + 2) * self.scale
+    }
+
+    /// Expire old messages and return the rows of the image to redraw,
+    /// which cover texts to draw or to erase.
+    pub fn update(&mut self, now: u64) -> Option<Range<usize>> {
+        if let Some((_, expiry)) = self.message.as_ref() {
+            if now >= *expiry {
+                self.message = None;
+            }
+        }
+
+        let visible = self.message.is_some() || self.status.is_some();
+        let redraw = visible || self.drawn;
+        self.drawn = visible;
+
+        if redraw {
+            Some(self.rows())
+        } else {
+            None
+        }
+    }
+
+    /// Draw texts on the `rows` of the image.
+    pub fn draw(&self, buffer: &mut [u32], size: (usize, usize), rows: Range<usize>) {
+        let margin = MARGIN * self.scale;
+
+        if let Some((text, _)) = self.message.as_ref() {
+            draw_text(
+                buffer,
+                size,
+                rows.clone(),
+                (margin, margin),
+                text,
+                self.scale,
+            );
+        }
+
+        if let Some(text) = self.status.as_ref() {
+            let x = size.0.saturating_sub(text_width(text, self.scale) + margin);
+            draw_text(buffer, size, rows, (x, margin), text, self.scale);
+        }
+    }
+}
+
+/// Width of the box around the text, which has a padding of a dot.
+fn text_width(text: &str, scale: usize) -> usize {
+    (text.chars().count() * (font::WIDTH + 1) + 1) * scale
+}
+
+/// Draw the text in a dark box at `pos`, clipped to the `rows`.
+fn draw_text(
+    buffer: &mut [u32],
+    (w, h): (usize, usize),
+    rows: Range<usize>,
+    (x0, y0): (usize, usize),
+    text: &str,
+    scale: usize,
+) {
+    let glyphs: Vec<_> = text.chars().map(font::glyph).collect();
+    let cell = font::WIDTH + 1;
+
+    let boxw = text_width(text, scale);
+    let boxh = (font::HEIGHT + 2) * scale;
+
+    for y in rows.start.max(y0)..rows.end.min(y0 + boxh).min(h) {
+        let dy = (y - y0) / scale;
+
+        for x in x0..(x0 + boxw).min(w) {
+            let dx = (x - x0) / scale;
+
+            let on = dy >= 1 && dy <= font::HEIGHT && dx >= 1 && {
+                let (index, cx) = ((dx - 1) / cell, (dx - 1) % cell);
+                cx < font::WIDTH && glyphs[index][dy - 1] & (1 << (font::WIDTH - 1 - cx)) != 0
+            };
+
+            let col = &mut buffer[y * w + x];
+            *col = if on {
+                TEXT_COLOR
+            } else {
+                video::mix(*col, 0, 160)
+            };
+        }
+    }
+}
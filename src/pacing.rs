@@ -0,0 +1,137 @@
+use log::*;
+use uefi::{
+    prelude::*,
+    table::boot::{EventType, TimerTrigger, Tpl},
+    Event,
+};
+
+/// Duration of a Game Boy frame in nanoseconds (70224 cycles at 4194304 Hz).
+const FRAME_NS: u64 = 16_742_706;
+
+/// Lag after which the pacer gives up catching up and restarts from now.
+const MAX_LAG_NS: u64 = 100_000_000;
+
+/// Time left to spin after sleeping, as firmware timers are coarse.
+const SLEEP_MARGIN: u64 = 4_000;
+
+/// How to wait for the remaining time of each frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pacing {
+    /// Busy-wait in the emulator.
+    Spin,
+    /// Wait with `stall` of the boot services.
+    Stall,
+    /// Halt the CPU waiting for a timer event.
+    Sleep,
+}
+
+/// Keeps frames at the native frame rate, waiting for the remaining time of each frame.
+///
+/// Frames end on VBlank, which doesn't come while the game keeps the LCD off. The
+/// emulator calls `sched` at a steady rate of emulated time, so the calls per frame
+/// are measured while VBlank comes, and that many calls without one end a frame.
+pub struct Pacer {
+    pacing: Pacing,
+    /// Time the next frame should end, in nanoseconds.
+    deadline: u64,
+    timer: Option<Event>,
+    /// Calls since the last frame ended.
+    calls: u64,
+    /// Average calls per frame, or 0 until the first frame ends.
+    calls_per_frame: u64,
+}
+
+impl Pacer {
+    pub fn new(bt: &BootServices, pacing: Pacing) -> Self {
+        let timer = match pacing {
+            Pacing::Sleep => match bt
+                .create_event(EventType::TIMER, Tpl::APPLICATION, None)
+                .log_warning()
+            {
+                Ok(timer) => Some(timer),
+                Err(e) => {
+                    warn!("Couldn't create timer event: {:?}", e.status());
+                    None
+                }
+            },
+            _ => None,
+        };
+
+        info!("Pacing: {:?}", pacing);
+
+        Self {
+            pacing,
+            deadline: 0,
+            timer,
+            calls: 0,
+            calls_per_frame: 0,
+        }
+    }
+
+    /// Time in microseconds the frame which ended at `now` microseconds should end.
+    fn end(&mut self, now: u64) -> u64 {
+        let now = now * 1000;
+
+        self.deadline += FRAME_NS;
+
+        if now > self.deadline + MAX_LAG_NS || self.deadline > now + MAX_LAG_NS {
+            self.deadline = now;
+        }
+
+        self.deadline / 1000
+    }
+
+    /// Called on each `sched` of the emulator, with `vblank` set if a frame was drawn
+    /// since the last call. Waits for the rest of the frame if one ended.
+    pub fn sched(&mut self, bt: &BootServices, vblank: bool, clock: impl FnMut() -> u64) {
+        self.calls += 1;
+
+        if vblank {
+            self.calls_per_frame = if self.calls_per_frame == 0 {
+                self.calls
+            } else {
+                (self.calls_per_frame * 7 + self.calls) / 8
+            };
+        } else if self.calls_per_frame == 0 || self.calls < self.calls_per_frame {
+            return;
+        }
+
+        self.calls = 0;
+        self.frame(bt, clock);
+    }
+
+    /// Wait for the rest of the frame which just ended.
+    fn frame(&mut self, bt: &BootServices, mut clock: impl FnMut() -> u64) {
+        if self.pacing == Pacing::Spin {
+            return;
+        }
+
+        let end = self.end(clock());
+
+        if let Some(timer) = self.timer {
+            let wait = end.saturating_sub(clock());
+            if wait > SLEEP_MARGIN {
+                sleep(bt, timer, wait - SLEEP_MARGIN);
+            }
+        }
+
+        let wait = end.saturating_sub(clock());
+        bt.stall(wait as usize);
+    }
+}
+
+/// Halt until the timer expires after `micros` microseconds.
+fn sleep(bt: &BootServices, timer: Event, micros: u64) {
+    // The timer is in 100ns units.
+    if let Err(e) = bt
+        .set_timer(timer, TimerTrigger::Relative(micros * 10))
+        .log_warning()
+    {
+        warn!("Couldn't set timer: {:?}", e.status());
+        return;
+    }
+
+    if let Err(e) = bt.wait_for_event(&mut [timer]).log_warning() {
+        warn!("Couldn't wait for timer: {:?}", e.status());
+    }
+}
@@ -0,0 +1,95 @@
+use alloc::{vec, vec::Vec};
+
+/// Colours of the four DMG shades, from the lightest to the darkest.
+pub type Shades = [u32; 4];
+
+/// Colours the emulator draws the four DMG shades with.
+const CORE: Shades = [0xdd_dddd, 0xaa_aaaa, 0x88_8888, 0x55_5555];
+
+const CLASSIC: Shades = [0x9b_bc0f, 0x8b_ac0f, 0x30_6230, 0x0f_380f];
+const GRAYSCALE: Shades = [0xff_ffff, 0xaa_aaaa, 0x55_5555, 0x00_0000];
+const POCKET: Shades = [0xc4_cfa1, 0x8b_956d, 0x4d_533c, 0x1f_1f1f];
+
+/// Names of the palettes available without a user-defined one.
+pub const NAMES: &[&str] = &["original", "classic", "grayscale", "pocket"];
+
+/// Parse shades like `e0f8d0, 88c070, 346856, 081820`.
+pub fn parse_shades(s: &str) -> Option<Shades> {
+    let mut shades = [0; 4];
+    let mut cols = s.split(',').map(|col| col.trim());
+
+    for shade in shades.iter_mut() {
+        let col = cols.next()?;
+        let col = col.trim_start_matches('#');
+        if col.len() != 6 {
+            return None;
+        }
+        *shade = u32::from_str_radix(col, 16).ok()?;
+    }
+
+    match cols.next() {
+        Some(_) => None,
+        None => Some(shades),
+    }
+}
+
+fn luma(col: u32) -> i32 {
+    let r = (col >> 16) & 0xff;
+    let g = (col >> 8) & 0xff;
+    let b = col & 0xff;
+
+    ((r * 2 + g * 5 + b) / 8) as i32
+}
+
+/// Classify a colour from the emulator into one of the four shades, as the emulator's
+/// shade of the nearest brightness. Its shades aren't spread evenly over the range.
+fn shade(col: u32) -> usize {
+    let target = luma(col);
+
+    (0..CORE.len())
+        .min_by_key(|&i| (luma(CORE[i]) - target).abs())
+        .unwrap_or(0)
+}
+
+/// Palettes selectable while playing.
+pub struct Palettes {
+    list: Vec<(&'static str, Option<Shades>)>,
+    current: usize,
+}
+
+impl Palettes {
+    pub fn new(name: &str, custom: Option<Shades>) -> Self {
+        let mut list = vec![
+            ("original", None),
+            ("classic", Some(CLASSIC)),
+            ("grayscale", Some(GRAYSCALE)),
+            ("pocket", Some(POCKET)),
+        ];
+
+        if let Some(custom) = custom {
+            list.push(("custom", Some(custom)));
+        }
+
+        let current = list.iter().position(|(n, _)| *n == name).unwrap_or(0);
+
+        Self { list, current }
+    }
+
+    /// Switch to the next palette, returning its name.
+    pub fn next(&mut self) -> &'static str {
+        self.current = (self.current + 1) % self.list.len();
+        self.name()
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.list[self.current].0
+    }
+
+    /// Map a colour from the emulator to the current palette.
+    pub fn map(&self, col: u32) -> u32 {
+        match self.list[self.current].1 {
+            Some(shades) => shades[shade(col)],
+            None => col,
+        }
+    }
+}
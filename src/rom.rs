@@ -0,0 +1,86 @@
+use crate::fs::Volume;
+use alloc::{format, string::String, vec::Vec};
+use log::*;
+
+const ROM_DIR: &str = "roms";
+
+/// Range of the title in the cartridge header.
+const TITLE_START: usize = 0x134;
+const TITLE_END: usize = 0x144;
+
+pub struct Rom {
+    /// Path to the ROM file on the volume, or `None` for the embedded ROM.
+    pub path: Option<String>,
+    pub data: Vec<u8>,
+}
+
+/// ROM file found on the volume.
+pub struct Entry {
+    pub path: String,
+    pub title: String,
+}
+
+fn is_rom(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    name.ends_with(".gb") || name.ends_with(".gbc")
+}
+
+/// Extract the title from the cartridge header.
+pub fn title(data: &[u8]) -> Option<String> {
+    let title = data.get(TITLE_START..TITLE_END)?;
+
+    let title: String = title
+        .iter()
+        .take_while(|&&b| b != 0)
+        .filter(|b| b.is_ascii_graphic() || **b == b' ')
+        .map(|&b| b as char)
+        .collect();
+
+    let title = title.trim();
+
+    if title.is_empty() {
+        None
+    } else {
+        Some(title.into())
+    }
+}
+
+/// Find ROM files under `\roms\` on the volume.
+pub fn scan(vol: &mut Volume) -> Vec<Entry> {
+    let mut names: Vec<_> = vol
+        .list(ROM_DIR)
+        .into_iter()
+        .filter(|name| is_rom(name))
+        .collect();
+    names.sort();
+
+    names
+        .into_iter()
+        .map(|name| {
+            let path = format!("{}\\{}", ROM_DIR, name);
+            let title = vol
+                .read_head(&path, TITLE_END)
+                .and_then(|head| title(&head))
+                .unwrap_or(name);
+            Entry { path, title }
+        })
+        .collect()
+}
+
+pub fn load(vol: &mut Volume, path: &str) -> Option<Rom> {
+    info!("Loading {}", path);
+
+    vol.read(path).ok().map(|data| Rom {
+        path: Some(path.into()),
+        data,
+    })
+}
+
+pub fn embedded() -> Rom {
+    info!("Using the embedded ROM");
+
+    Rom {
+        path: None,
+        data: include_bytes!("roms/zelda.gb").to_vec(),
+    }
+}
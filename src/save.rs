@@ -0,0 +1,282 @@
+use crate::{fs::Volume, settings::SaveBackend};
+use alloc::{format, string::String, vec, vec::Vec};
+use log::*;
+use uefi::{
+    prelude::*,
+    table::runtime::{RuntimeServices, VariableAttributes},
+    CStr16, Guid,
+};
+
+/// Vendor GUID of the UEFI variables holding cartridge RAM.
+const VENDOR: Guid = Guid::from_values(
+    0x5b2f_d1c6,
+    0x8a4e,
+    0x4f3b,
+    0x9d61,
+    [0x2c, 0x7e, 0x0a, 0x3b, 0x9f, 0x14],
+);
+
+/// Interval to write dirty cartridge RAM to a file.
+const FILE_FLUSH_INTERVAL: u64 = 5_000_000;
+
+/// Interval to write dirty cartridge RAM to UEFI variables. Much longer than for files,
+/// as games using the RAM as work memory would otherwise wear the firmware flash.
+const VARIABLE_FLUSH_INTERVAL: u64 = 300_000_000;
+
+/// Size of each UEFI variable, as firmware limits the size of a single variable.
+const CHUNK_SIZE: usize = 8 * 1024;
+
+enum Backend {
+    File {
+        vol: Volume,
+        path: String,
+    },
+    Variable {
+        name: String,
+        /// Content of the variables as last read or written, to skip unchanged chunks.
+        stored: Vec<u8>,
+    },
+}
+
+/// Battery-backed cartridge RAM.
+///
+/// Writes from the emulator are only cached, and go to the backend on `flush`.
+pub struct Save {
+    backend: Backend,
+    ram: Vec<u8>,
+    dirty: bool,
+    /// Loading failed, so writes are dropped to keep the existing save intact.
+    failed: bool,
+}
+
+fn file_name(path: &str) -> &str {
+    match path.rfind('\\') {
+        Some(i) => &path[i + 1..],
+        None => path,
+    }
+}
+
+fn stem(path: &str) -> &str {
+    match path.rfind('.') {
+        Some(i) => &path[..i],
+        None => path,
+    }
+}
+
+/// Name of the variable holding the `index`-th chunk, as a null-terminated UCS-2 string.
+fn var_name(name: &str, index: usize) -> Vec<u16> {
+    format!("{}.{}", name, index)
+        .encode_utf16()
+        .chain(core::iter::once(0))
+        .collect()
+}
+
+fn attributes() -> VariableAttributes {
+    VariableAttributes::NON_VOLATILE
+        | VariableAttributes::BOOTSERVICE_ACCESS
+        | VariableAttributes::RUNTIME_ACCESS
+}
+
+impl Save {
+    /// Save stored in `<romname>.sav` next to the ROM.
+    pub fn file(vol: Volume, rom_path: &str) -> Self {
+        Self::with_backend(Backend::File {
+            vol,
+            path: format!("{}.sav", stem(rom_path)),
+        })
+    }
+
+    /// Save stored in UEFI variables named after the ROM file.
+    pub fn variable(rom_path: &str) -> Self {
+        Self::with_backend(Backend::Variable {
+            name: format!("{}.sav", stem(file_name(rom_path))),
+            stored: Vec::new(),
+        })
+    }
+
+    fn with_backend(backend: Backend) -> Self {
+        Self {
+            backend,
+            ram: Vec::new(),
+            dirty: false,
+            failed: false,
+        }
+    }
+
+    pub fn new(backend: SaveBackend, vol: Volume, rom_path: &str) -> Self {
+        match backend {
+            SaveBackend::File => Self::file(vol, rom_path),
+            SaveBackend::Variable => Self::variable(rom_path),
+        }
+    }
+
+    pub fn load(&mut self, st: &SystemTable<Boot>, size: usize) -> Vec<u8> {
+        match self.load_backend(st, size) {
+            Some(ram) => {
+                self.ram = ram.clone();
+                self.dirty = false;
+                ram
+            }
+            None => {
+                warn!("Saving is disabled to keep the existing save");
+                self.failed = true;
+                vec![0; size]
+            }
+        }
+    }
+
+    /// Load the RAM, creating a zero-filled one if none is stored yet.
+    /// Returns `None` if the stored one exists but couldn't be read.
+    fn load_backend(&mut self, st: &SystemTable<Boot>, size: usize) -> Option<Vec<u8>> {
+        match &mut self.backend {
+            Backend::File { vol, path } => match vol.read(path) {
+                Ok(mut ram) => {
+                    info!("Loaded {}", path);
+                    ram.resize(size, 0);
+                    Some(ram)
+                }
+                Err(Status::NOT_FOUND) => {
+                    info!("Creating {}", path);
+                    let ram = vec![0; size];
+                    vol.write(path, &ram);
+                    Some(ram)
+                }
+                Err(status) => {
+                    warn!("Couldn't load {}: {:?}", path, status);
+                    None
+                }
+            },
+            Backend::Variable { name, stored } => {
+                let rt = st.runtime_services();
+                match load_variables(rt, name, size) {
+                    Ok(ram) => {
+                        info!("Loaded variable {}", name);
+                        *stored = ram.clone();
+                        Some(ram)
+                    }
+                    Err(Status::NOT_FOUND) => {
+                        info!("Creating variable {}", name);
+                        let ram = vec![0; size];
+                        store_variables(rt, name, &ram, stored);
+                        Some(ram)
+                    }
+                    Err(status) => {
+                        warn!("Couldn't load variable {}: {:?}", name, status);
+                        None
+                    }
+                }
+            }
+        }
+    }
+
+    /// Interval in microseconds to write dirty RAM to the backend, besides on exit.
+    pub fn interval(&self) -> u64 {
+        match self.backend {
+            Backend::File { .. } => FILE_FLUSH_INTERVAL,
+            Backend::Variable { .. } => VARIABLE_FLUSH_INTERVAL,
+        }
+    }
+
+    /// Update the cached RAM, marking it dirty if changed.
+    pub fn store(&mut self, ram: &[u8]) {
+        if self.failed {
+            return;
+        }
+
+        if self.ram.as_slice() != ram {
+            self.ram.clear();
+            self.ram.extend_from_slice(ram);
+            self.dirty = true;
+        }
+    }
+
+    /// Write the cached RAM to the backend if it's dirty. Returns `true` if written.
+    pub fn flush(&mut self, st: &SystemTable<Boot>) -> bool {
+        if !self.dirty {
+            return false;
+        }
+
+        let ram = &self.ram;
+
+        let saved = match &mut self.backend {
+            Backend::File { vol, path } => {
+                let saved = vol.write(path, ram);
+                if saved {
+                    info!("Saved {}", path);
+                }
+                saved
+            }
+            Backend::Variable { name, stored } => {
+                let saved = store_variables(st.runtime_services(), name, ram, stored);
+                if saved {
+                    info!("Saved variable {}", name);
+                }
+                saved
+            }
+        };
+
+        // Keep it dirty on failure to retry on the next flush.
+        self.dirty = !saved;
+
+        saved
+    }
+}
+
+/// Read the variables of the save named `name`.
+///
+/// Fails with `NOT_FOUND` only if no variable of the save exists. Chunks missing after
+/// the first are left zero-filled, like a save file shorter than the RAM.
+fn load_variables(rt: &RuntimeServices, name: &str, size: usize) -> Result<Vec<u8>, Status> {
+    let mut ram = vec![0; size];
+
+    for (i, chunk) in ram.chunks_mut(CHUNK_SIZE).enumerate() {
+        let var = var_name(name, i);
+        let var = CStr16::from_u16_with_nul(&var).map_err(|_| Status::INVALID_PARAMETER)?;
+
+        match rt.get_variable(var, &VENDOR, chunk).log_warning() {
+            Ok(_) => {}
+            Err(e) if e.status() == Status::NOT_FOUND && i > 0 => {}
+            Err(e) => {
+                if e.status() != Status::NOT_FOUND {
+                    warn!("Couldn't read variable {}.{}: {:?}", name, i, e.status());
+                }
+                return Err(e.status());
+            }
+        }
+    }
+
+    Ok(ram)
+}
+
+/// Write the chunks of `ram` which differ from `stored`, updating `stored` as written.
+///
+/// Rewriting unchanged chunks would only wear the firmware flash.
+fn store_variables(rt: &RuntimeServices, name: &str, ram: &[u8], stored: &mut Vec<u8>) -> bool {
+    for (i, chunk) in ram.chunks(CHUNK_SIZE).enumerate() {
+        let range = i * CHUNK_SIZE..i * CHUNK_SIZE + chunk.len();
+        if stored.get(range.clone()) == Some(chunk) {
+            continue;
+        }
+
+        let var = var_name(name, i);
+        let var = match CStr16::from_u16_with_nul(&var) {
+            Ok(var) => var,
+            Err(_) => return false,
+        };
+
+        if let Err(e) = rt
+            .set_variable(var, &VENDOR, attributes(), chunk)
+            .log_warning()
+        {
+            warn!("Couldn't write variable {}.{}: {:?}", name, i, e.status());
+            return false;
+        }
+
+        if stored.len() < range.end {
+            stored.resize(range.end, 0);
+        }
+        stored[range].copy_from_slice(chunk);
+    }
+
+    true
+}
@@ -0,0 +1,39 @@
+//! Serial port output, which stays visible while the graphics mode hides the console.
+
+use log::*;
+use uefi::{prelude::*, proto::console::serial::Serial};
+
+pub struct SerialLog {
+    port: Option<&'static mut Serial>,
+}
+
+impl SerialLog {
+    pub fn new(bt: &BootServices) -> Self {
+        let port = match bt.locate_protocol::<Serial>().log_warning() {
+            Ok(port) => Some(unsafe { &mut *port.get() }),
+            Err(_) => {
+                info!("No serial port available");
+                None
+            }
+        };
+
+        Self { port }
+    }
+
+    /// Write a line to the serial port. Returns `false` if it couldn't be written.
+    pub fn line(&mut self, msg: &str) -> bool {
+        let port = match self.port.as_mut() {
+            Some(port) => port,
+            None => return false,
+        };
+
+        for data in [msg.as_bytes(), b"\r\n"].iter() {
+            if let Err(e) = port.write(data).log_warning() {
+                warn!("Couldn't write to serial port: {:?}", e.status());
+                return false;
+            }
+        }
+
+        true
+    }
+}
@@ -0,0 +1,215 @@
+use crate::{
+    config::Config,
+    fs::Volume,
+    pacing::Pacing,
+    palette::{self, Shades},
+    time::Source,
+    video::{Effect, Filter, Scaling},
+};
+use alloc::{format, string::String};
+use uefi::prelude::*;
+
+const SETTINGS_PATH: &str = "stickboy\\stickboy.cfg";
+
+/// Where battery-backed cartridge RAM is stored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SaveBackend {
+    /// `<romname>.sav` next to the ROM.
+    File,
+    /// Non-volatile UEFI variables, for read-only boot media.
+    Variable,
+}
+
+/// Settings loaded from `\stickboy\stickboy.cfg`.
+pub struct Settings {
+    pub save: SaveBackend,
+    /// Resolution of the graphics mode to use instead of the automatic choice.
+    pub mode: Option<(usize, usize)>,
+    pub scaling: Scaling,
+    pub filter: Filter,
+    pub effect: Effect,
+    /// Name of the palette used at start.
+    pub palette: String,
+    /// User-defined palette, selectable as `custom`.
+    pub custom_palette: Option<Shades>,
+    /// Blend consecutive frames to emulate LCD ghosting.
+    pub blend: bool,
+    /// Show frame rates on the screen.
+    pub fps: bool,
+    /// Log frame rates once per second.
+    pub fps_log: bool,
+    /// Time source of the emulator.
+    pub clock: Source,
+    /// How to wait for the remaining time of each frame.
+    pub pacing: Pacing,
+}
+
+/// Parse `on` or `off`.
+fn parse_switch(s: &str) -> Option<bool> {
+    match s {
+        "on" => Some(true),
+        "off" => Some(false),
+        _ => None,
+    }
+}
+
+/// Parse a resolution like `1024x768`.
+fn parse_resolution(s: &str) -> Option<(usize, usize)> {
+    let mut wh = s.splitn(2, 'x');
+    let w = wh.next()?.trim().parse().ok()?;
+    let h = wh.next()?.trim().parse().ok()?;
+    Some((w, h))
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            save: if cfg!(feature = "nv_save") {
+                SaveBackend::Variable
+            } else {
+                SaveBackend::File
+            },
+            mode: None,
+            scaling: Scaling::Integer,
+            filter: Filter::Nearest,
+            effect: Effect::None,
+            palette: "original".into(),
+            custom_palette: None,
+            blend: false,
+            fps: false,
+            fps_log: false,
+            clock: if cfg!(feature = "uefi_time_source") {
+                Source::Rtc
+            } else {
+                Source::Auto
+            },
+            pacing: Pacing::Spin,
+        }
+    }
+}
+
+impl Settings {
+    /// Load settings, keeping defaults for ones not in the file. Errors are shown on the console.
+    pub fn load(st: &SystemTable<Boot>, vol: &mut Volume) -> Self {
+        let mut settings = Self::default();
+
+        let mut cfg = match Config::load(vol, SETTINGS_PATH) {
+            Some(cfg) => cfg,
+            None => return settings,
+        };
+
+        for entry in &cfg.entries {
+            let value = entry.value.as_str();
+
+            match entry.name.as_str() {
+                "save" => match value {
+                    "file" => settings.save = SaveBackend::File,
+                    "variable" => settings.save = SaveBackend::Variable,
+                    _ => cfg.errors.push(entry.error(&format!(
+                        "`save` must be `file` or `variable`, not `{}`",
+                        value
+                    ))),
+                },
+                "mode" => match parse_resolution(value) {
+                    Some(res) => settings.mode = Some(res),
+                    None => cfg.errors.push(entry.error(&format!(
+                        "`mode` must be a resolution like `1024x768`, not `{}`",
+                        value
+                    ))),
+                },
+                "scaling" => match value {
+                    "integer" => settings.scaling = Scaling::Integer,
+                    "fit" => settings.scaling = Scaling::Fit,
+                    _ => cfg.errors.push(entry.error(&format!(
+                        "`scaling` must be `integer` or `fit`, not `{}`",
+                        value
+                    ))),
+                },
+                "filter" => match value {
+                    "nearest" => settings.filter = Filter::Nearest,
+                    "bilinear" => settings.filter = Filter::Bilinear,
+                    _ => cfg.errors.push(entry.error(&format!(
+                        "`filter` must be `nearest` or `bilinear`, not `{}`",
+                        value
+                    ))),
+                },
+                "effect" => match value {
+                    "none" => settings.effect = Effect::None,
+                    "grid" => settings.effect = Effect::Grid,
+                    "scanlines" => settings.effect = Effect::Scanlines,
+                    "scale2x" => settings.effect = Effect::Scale2x,
+                    _ => cfg.errors.push(entry.error(&format!(
+                        "`effect` must be `none`, `grid`, `scanlines` or `scale2x`, not `{}`",
+                        value
+                    ))),
+                },
+                "palette" => settings.palette = value.into(),
+                "custom_palette" => match palette::parse_shades(value) {
+                    Some(shades) => settings.custom_palette = Some(shades),
+                    None => cfg.errors.push(entry.error(&format!(
+                        "`custom_palette` must be four colours like `e0f8d0, 88c070, 346856, 081820`, not `{}`",
+                        value
+                    ))),
+                },
+                "blend" => match parse_switch(value) {
+                    Some(blend) => settings.blend = blend,
+                    None => cfg.errors.push(entry.error(&format!(
+                        "`blend` must be `on` or `off`, not `{}`",
+                        value
+                    ))),
+                },
+                "fps" => match parse_switch(value) {
+                    Some(fps) => settings.fps = fps,
+                    None => cfg.errors.push(entry.error(&format!(
+                        "`fps` must be `on` or `off`, not `{}`",
+                        value
+                    ))),
+                },
+                "fps_log" => match parse_switch(value) {
+                    Some(fps_log) => settings.fps_log = fps_log,
+                    None => cfg.errors.push(entry.error(&format!(
+                        "`fps_log` must be `on` or `off`, not `{}`",
+                        value
+                    ))),
+                },
+                "clock" => match value {
+                    "auto" => settings.clock = Source::Auto,
+                    "tsc" => settings.clock = Source::Tsc,
+                    "rtc" => settings.clock = Source::Rtc,
+                    "hpet" => settings.clock = Source::Hpet,
+                    "pmtimer" => settings.clock = Source::PmTimer,
+                    _ => cfg.errors.push(entry.error(&format!(
+                        "`clock` must be `auto`, `tsc`, `rtc`, `hpet` or `pmtimer`, not `{}`",
+                        value
+                    ))),
+                },
+                "pacing" => match value {
+                    "spin" => settings.pacing = Pacing::Spin,
+                    "stall" => settings.pacing = Pacing::Stall,
+                    "sleep" => settings.pacing = Pacing::Sleep,
+                    _ => cfg.errors.push(entry.error(&format!(
+                        "`pacing` must be `spin`, `stall` or `sleep`, not `{}`",
+                        value
+                    ))),
+                },
+                name => cfg
+                    .errors
+                    .push(entry.error(&format!("unknown setting `{}`", name))),
+            }
+        }
+
+        let custom = settings.custom_palette.is_some() && settings.palette == "custom";
+        if !custom && !palette::NAMES.contains(&settings.palette.as_str()) {
+            cfg.errors.push(format!(
+                "unknown palette `{}`; use `custom` with `custom_palette`, or one of {:?}",
+                settings.palette,
+                palette::NAMES
+            ));
+            settings.palette = "original".into();
+        }
+
+        cfg.report(st);
+
+        settings
+    }
+}
@@ -0,0 +1,345 @@
+use crate::acpi;
+use log::*;
+use uefi::{prelude::*, table::runtime::RuntimeServices};
+
+#[cfg(target_arch = "x86")]
+use core::arch::x86::{__cpuid, _rdtsc};
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::{__cpuid, _rdtsc};
+
+/// Frequency of the ACPI PM timer.
+const PM_TIMER_FREQ: u64 = 3_579_545;
+
+/// HPET register offsets.
+const HPET_CAPABILITIES: usize = 0x00;
+const HPET_CONFIG: usize = 0x10;
+const HPET_COUNTER: usize = 0xf0;
+
+/// Duration to measure TSC ticks against `stall` on calibration.
+const CALIBRATION_PERIOD: u64 = 50_000;
+
+pub fn tsc() -> u64 {
+    unsafe { _rdtsc() as u64 }
+}
+
+/// Check if the TSC runs at a constant rate in all power states.
+fn invariant_tsc() -> bool {
+    let max = unsafe { __cpuid(0x8000_0000) }.eax;
+    if max < 0x8000_0007 {
+        return false;
+    }
+
+    unsafe { __cpuid(0x8000_0007) }.edx & (1 << 8) != 0
+}
+
+/// TSC frequency from CPUID leaf 0x15, if the CPU reports the crystal frequency.
+fn cpuid_freq() -> Option<u64> {
+    let max = unsafe { __cpuid(0) }.eax;
+    if max < 0x15 {
+        return None;
+    }
+
+    let leaf = unsafe { __cpuid(0x15) };
+    let (denom, numer, crystal) = (leaf.eax as u64, leaf.ebx as u64, leaf.ecx as u64);
+
+    if denom == 0 || numer == 0 || crystal == 0 {
+        None
+    } else {
+        Some(crystal * numer / denom)
+    }
+}
+
+/// TSC frequency measured against the firmware stall.
+fn stall_freq(bt: &BootServices) -> u64 {
+    let start = tsc();
+    bt.stall(CALIBRATION_PERIOD as usize);
+    let ticks = tsc().wrapping_sub(start);
+
+    ticks * 1_000_000 / CALIBRATION_PERIOD
+}
+
+/// Time source converting TSC ticks to microseconds.
+pub struct Tsc {
+    /// Ticks per second.
+    freq: u64,
+}
+
+impl Tsc {
+    pub fn calibrate(bt: &BootServices) -> Self {
+        let freq = match cpuid_freq() {
+            Some(freq) => {
+                info!("TSC frequency from CPUID: {} Hz", freq);
+                freq
+            }
+            None => {
+                let freq = stall_freq(bt);
+                info!("TSC frequency from calibration: {} Hz", freq);
+                freq
+            }
+        };
+
+        Self { freq: freq.max(1) }
+    }
+
+    pub fn micros(&self) -> u64 {
+        (tsc() as u128 * 1_000_000 / self.freq as u128) as u64
+    }
+}
+
+/// Extend a wrapping counter narrower than 64 bits.
+struct Counter {
+    mask: u64,
+    last: u64,
+    high: u64,
+}
+
+impl Counter {
+    fn new(bits: u32) -> Self {
+        Self {
+            mask: if bits >= 64 { !0 } else { (1 << bits) - 1 },
+            last: 0,
+            high: 0,
+        }
+    }
+
+    fn extend(&mut self, raw: u64) -> u64 {
+        let raw = raw & self.mask;
+
+        if raw < self.last {
+            self.high = self.high.wrapping_add(self.mask).wrapping_add(1);
+        }
+        self.last = raw;
+
+        self.high.wrapping_add(raw)
+    }
+}
+
+/// Time source based on the High Precision Event Timer.
+pub struct Hpet {
+    base: usize,
+    /// Counter period in femtoseconds.
+    period: u64,
+    counter: Counter,
+}
+
+impl Hpet {
+    pub fn new(st: &SystemTable<Boot>) -> Option<Self> {
+        let base = acpi::hpet(st)?;
+
+        let caps = unsafe { ((base + HPET_CAPABILITIES) as *const u64).read_volatile() };
+        let period = caps >> 32;
+        // COUNT_SIZE_CAP
+        let bits = if caps & (1 << 13) != 0 { 64 } else { 32 };
+
+        if period == 0 {
+            return None;
+        }
+
+        // Start the counter if the firmware didn't.
+        unsafe {
+            let config = (base + HPET_CONFIG) as *mut u64;
+            config.write_volatile(config.read_volatile() | 1);
+        }
+
+        info!("HPET at {:#x}: {} fs period, {}-bit", base, period, bits);
+
+        Some(Self {
+            base,
+            period,
+            counter: Counter::new(bits),
+        })
+    }
+
+    pub fn micros(&mut self) -> u64 {
+        let raw = unsafe { ((self.base + HPET_COUNTER) as *const u64).read_volatile() };
+        let ticks = self.counter.extend(raw);
+
+        (ticks as u128 * self.period as u128 / 1_000_000_000) as u64
+    }
+}
+
+fn inl(port: u16) -> u32 {
+    let val: u32;
+    unsafe { asm!("inl %dx, %eax" : "={eax}"(val) : "{dx}"(port) :: "volatile") };
+    val
+}
+
+/// Time source based on the ACPI PM timer.
+pub struct PmTimer {
+    port: u16,
+    counter: Counter,
+}
+
+impl PmTimer {
+    pub fn new(st: &SystemTable<Boot>) -> Option<Self> {
+        let (port, ext) = acpi::pm_timer(st)?;
+        let bits = if ext { 32 } else { 24 };
+
+        info!("PM timer at port {:#x}, {}-bit", port, bits);
+
+        Some(Self {
+            port,
+            counter: Counter::new(bits),
+        })
+    }
+
+    pub fn micros(&mut self) -> u64 {
+        let ticks = self.counter.extend(inl(self.port) as u64);
+
+        (ticks as u128 * 1_000_000 / PM_TIMER_FREQ as u128) as u64
+    }
+}
+
+pub fn days_from_civil(y: i64, m: i64, d: i64) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let doy = (153 * (m + if m > 2 { -3 } else { 9 }) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Read the RTC in microseconds since the Unix epoch.
+pub fn rtc(rt: &RuntimeServices) -> u64 {
+    let t = rt
+        .get_time()
+        .expect("Couldn't get time")
+        .expect("Couln't extract time");
+
+    let days = days_from_civil(t.year() as i64, t.month() as i64, t.day() as i64);
+
+    (days as u64) * 24 * 3600_000_000
+        + (t.hour() as u64) * 3600_000_000
+        + (t.minute() as u64) * 60_000_000
+        + (t.second() as u64) * 1000_000
+        + (t.nanosecond() / 1000) as u64
+}
+
+/// Time source based on the UEFI RTC.
+///
+/// Most firmware only reports whole seconds, so the time within a second is
+/// interpolated with the TSC. The time never goes backwards, even if the RTC does.
+pub struct Rtc {
+    /// Last RTC value read.
+    base: u64,
+    /// TSC time when the RTC value changed.
+    since: u64,
+    /// Last time returned.
+    last: u64,
+}
+
+impl Rtc {
+    pub fn new() -> Self {
+        Self {
+            base: 0,
+            since: 0,
+            last: 0,
+        }
+    }
+
+    pub fn micros(&mut self, rt: &RuntimeServices, tsc: &Tsc) -> u64 {
+        let now = rtc(rt);
+        let clk = tsc.micros();
+
+        if now != self.base {
+            self.base = now;
+            self.since = clk;
+        }
+
+        let t = self.base + clk.wrapping_sub(self.since).min(999_999);
+        self.last = self.last.max(t);
+        self.last
+    }
+}
+
+/// Kind of time source.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Source {
+    /// Choose at runtime.
+    Auto,
+    Tsc,
+    Rtc,
+    Hpet,
+    PmTimer,
+}
+
+/// Time source of the emulator.
+///
+/// The time is anchored to the wall-clock time of the RTC, in microseconds since
+/// the Unix epoch, rather than starting from an arbitrary counter value.
+pub struct Clock {
+    source: Source,
+    /// Difference between the wall-clock time and the time source.
+    offset: u64,
+    tsc: Tsc,
+    rtc: Rtc,
+    hpet: Option<Hpet>,
+    pm_timer: Option<PmTimer>,
+}
+
+impl Clock {
+    pub fn new(st: &SystemTable<Boot>, source: Source) -> Self {
+        let tsc = Tsc::calibrate(st.boot_services());
+
+        // Prefer the invariant TSC, which is the cheapest to read, and then
+        // the timers with higher resolution. The timers are only probed if they may be
+        // used, as probing the HPET starts its counter, which the firmware owns.
+        let fallback = source == Source::Auto && !invariant_tsc();
+        let hpet = if source == Source::Hpet || fallback {
+            Hpet::new(st)
+        } else {
+            None
+        };
+        let pm_timer = if source == Source::PmTimer || (fallback && hpet.is_none()) {
+            PmTimer::new(st)
+        } else {
+            None
+        };
+
+        let source = match source {
+            Source::Auto if !fallback => Source::Tsc,
+            Source::Auto if hpet.is_some() => Source::Hpet,
+            Source::Auto if pm_timer.is_some() => Source::PmTimer,
+            Source::Auto => Source::Rtc,
+            Source::Hpet if hpet.is_none() => {
+                warn!("HPET is not available");
+                Source::Rtc
+            }
+            Source::PmTimer if pm_timer.is_none() => {
+                warn!("PM timer is not available");
+                Source::Rtc
+            }
+            source => source,
+        };
+
+        info!("Time source: {:?}", source);
+
+        let mut clock = Self {
+            source,
+            offset: 0,
+            tsc,
+            rtc: Rtc::new(),
+            hpet,
+            pm_timer,
+        };
+
+        let wall = rtc(st.runtime_services());
+        clock.offset = wall.wrapping_sub(clock.source_micros(st));
+
+        clock
+    }
+
+    /// Current time in microseconds since the Unix epoch.
+    pub fn micros(&mut self, st: &SystemTable<Boot>) -> u64 {
+        self.source_micros(st).wrapping_add(self.offset)
+    }
+
+    fn source_micros(&mut self, st: &SystemTable<Boot>) -> u64 {
+        match self.source {
+            Source::Tsc | Source::Auto => self.tsc.micros(),
+            Source::Rtc => self.rtc.micros(st.runtime_services(), &self.tsc),
+            Source::Hpet => self.hpet.as_mut().map(|t| t.micros()).unwrap_or(0),
+            Source::PmTimer => self.pm_timer.as_mut().map(|t| t.micros()).unwrap_or(0),
+        }
+    }
+}
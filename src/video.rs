@@ -0,0 +1,177 @@
+use core::ops::Range;
+use rgy::hardware::{VRAM_HEIGHT, VRAM_WIDTH};
+use uefi::proto::console::gop::{BltPixel, PixelFormat};
+
+/// How the screen is scaled to the graphics mode resolution.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scaling {
+    /// Largest integer multiple fitting in the resolution.
+    Integer,
+    /// Fill the resolution as much as possible keeping the aspect ratio.
+    Fit,
+}
+
+/// How pixels are sampled when scaling.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Filter {
+    Nearest,
+    Bilinear,
+}
+
+/// Post-processing applied to the scaled image to look like the original LCD.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Effect {
+    None,
+    /// Darken the borders of each dot.
+    Grid,
+    /// Darken the bottom of each line.
+    Scanlines,
+    /// Smooth diagonal edges with Scale2x, instead of the filter.
+    Scale2x,
+}
+
+pub fn pix(col: u32) -> BltPixel {
+    let r = (col >> 16) as u8;
+    let g = (col >> 8) as u8;
+    let b = col as u8;
+    BltPixel::new(r, g, b)
+}
+
+/// Convert a colour to the framebuffer pixel value of the format.
+pub fn fbpix(col: u32, format: PixelFormat) -> u32 {
+    match format {
+        // Bytes in memory are B, G, R, reserved; the same as the colour.
+        PixelFormat::BGR => col & 0xff_ffff,
+        // Bytes in memory are R, G, B, reserved.
+        _ => ((col & 0xff) << 16) | (col & 0xff00) | ((col >> 16) & 0xff),
+    }
+}
+
+/// Compute the size and the position of the scaled image centred on the screen.
+pub fn layout(res: (usize, usize), scaling: Scaling) -> ((usize, usize), (usize, usize)) {
+    let (resw, resh) = res;
+
+    let size = match scaling {
+        Scaling::Integer => {
+            let scale = (resw / VRAM_WIDTH).min(resh / VRAM_HEIGHT).max(1);
+            (VRAM_WIDTH * scale, VRAM_HEIGHT * scale)
+        }
+        Scaling::Fit => {
+            if resw * VRAM_HEIGHT >= resh * VRAM_WIDTH {
+                (resh * VRAM_WIDTH / VRAM_HEIGHT, resh)
+            } else {
+                (resw, resw * VRAM_HEIGHT / VRAM_WIDTH)
+            }
+        }
+    };
+
+    let pos = (
+        resw.saturating_sub(size.0) / 2,
+        resh.saturating_sub(size.1) / 2,
+    );
+
+    (size, pos)
+}
+
+/// Mix two colours; `t` is the weight of `b` out of 256.
+pub fn mix(a: u32, b: u32, t: u32) -> u32 {
+    let ch = |shift: u32| {
+        let a = (a >> shift) & 0xff;
+        let b = (b >> shift) & 0xff;
+        ((a * (256 - t) + b * t) >> 8) << shift
+    };
+    ch(16) | ch(8) | ch(0)
+}
+
+/// Map a destination coordinate to a source coordinate in 1/256 pixel units,
+/// aligning pixel centres.
+fn source(d: usize, dlen: usize, slen: usize) -> (usize, u32) {
+    let s = ((2 * d + 1) * slen * 128 / dlen).saturating_sub(128);
+    ((s >> 8).min(slen - 1), (s & 0xff) as u32)
+}
+
+fn bilinear(vram: &[u32], x: usize, y: usize, (w, h): (usize, usize)) -> u32 {
+    let (x0, fx) = source(x, w, VRAM_WIDTH);
+    let (y0, fy) = source(y, h, VRAM_HEIGHT);
+    let x1 = (x0 + 1).min(VRAM_WIDTH - 1);
+    let y1 = (y0 + 1).min(VRAM_HEIGHT - 1);
+
+    let top = mix(vram[y0 * VRAM_WIDTH + x0], vram[y0 * VRAM_WIDTH + x1], fx);
+    let bottom = mix(vram[y1 * VRAM_WIDTH + x0], vram[y1 * VRAM_WIDTH + x1], fx);
+    mix(top, bottom, fy)
+}
+
+/// Check if the destination coordinate is the last one mapped to a source coordinate.
+fn edge(d: usize, dlen: usize, slen: usize) -> bool {
+    (d + 1) * slen / dlen != d * slen / dlen
+}
+
+fn scale2x(vram: &[u32], x: usize, y: usize, (w, h): (usize, usize)) -> u32 {
+    // Source coordinate in the image scaled by 2.
+    let sx2 = x * VRAM_WIDTH * 2 / w;
+    let sy2 = y * VRAM_HEIGHT * 2 / h;
+    let (sx, sy) = (sx2 / 2, sy2 / 2);
+
+    let at = |x: usize, y: usize| vram[y * VRAM_WIDTH + x];
+
+    let e = at(sx, sy);
+    let b = at(sx, sy.saturating_sub(1));
+    let d = at(sx.saturating_sub(1), sy);
+    let f = at((sx + 1).min(VRAM_WIDTH - 1), sy);
+    let h = at(sx, (sy + 1).min(VRAM_HEIGHT - 1));
+
+    match (sx2 & 1, sy2 & 1) {
+        (0, 0) if d == b && b != f && d != h => d,
+        (1, 0) if b == f && b != d && f != h => f,
+        (0, 1) if d == h && d != b && h != f => d,
+        (1, 1) if h == f && h != d && f != b => f,
+        _ => e,
+    }
+}
+
+/// Rows of the scaled image affected by changes in the screen `lines`,
+/// including the neighbours sampled by filtering.
+pub fn rows(lines: Range<usize>, h: usize) -> Range<usize> {
+    let start = lines.start.saturating_sub(1);
+    let end = (lines.end + 1).min(VRAM_HEIGHT);
+
+    (start * h / VRAM_HEIGHT)..((end * h + VRAM_HEIGHT - 1) / VRAM_HEIGHT).min(h)
+}
+
+/// Screen lines mapped to the `rows` of the scaled image.
+pub fn lines(rows: Range<usize>, h: usize) -> Range<usize> {
+    (rows.start * VRAM_HEIGHT / h)..((rows.end * VRAM_HEIGHT + h - 1) / h).min(VRAM_HEIGHT)
+}
+
+/// Scale the screen to `size`, updating the `rows` of `buffer`.
+pub fn scale(
+    vram: &[u32],
+    buffer: &mut [u32],
+    size: (usize, usize),
+    filter: Filter,
+    effect: Effect,
+    rows: Range<usize>,
+) {
+    let (w, h) = size;
+
+    for y in rows {
+        let line = &mut buffer[y * w..(y + 1) * w];
+        let yedge = edge(y, h, VRAM_HEIGHT);
+
+        for (x, col) in line.iter_mut().enumerate() {
+            let c = match (effect, filter) {
+                (Effect::Scale2x, _) => scale2x(vram, x, y, size),
+                (_, Filter::Nearest) => {
+                    vram[(y * VRAM_HEIGHT / h) * VRAM_WIDTH + x * VRAM_WIDTH / w]
+                }
+                (_, Filter::Bilinear) => bilinear(vram, x, y, size),
+            };
+
+            *col = match effect {
+                Effect::Grid if yedge || edge(x, w, VRAM_WIDTH) => mix(c, 0, 64),
+                Effect::Scanlines if yedge => mix(c, 0, 96),
+                _ => c,
+            };
+        }
+    }
+}